@@ -21,7 +21,7 @@ use bevy_replicon_renet::{
 use bevy_replicon_snap::{
     interpolation::AppInterpolationExt,
     prediction::OwnerPredicted,
-    prediction::{AppPredictionExt, Predict},
+    prediction::{AppPredictionExt, Predict, PredictedInputWriter},
     NetworkOwner, SnapshotInterpolationPlugin,
 };
 use bevy_replicon_snap_macros::Interpolate;
@@ -197,7 +197,7 @@ impl SimpleBoxPlugin {
     }
 
     /// Reads player inputs and sends [`MoveCommandEvents`]
-    fn input_system(input: Res<ButtonInput<KeyCode>>, mut commands: Commands) {
+    fn input_system(input: Res<ButtonInput<KeyCode>>, mut inputs: PredictedInputWriter) {
         let mut direction = Vec2::ZERO;
         if input.pressed(KeyCode::ArrowRight) {
             direction.x += 1.0;
@@ -212,7 +212,7 @@ impl SimpleBoxPlugin {
             direction.y -= 1.0;
         }
         if direction != Vec2::ZERO {
-            commands.client_trigger(MoveDirection(direction.normalize_or_zero()));
+            inputs.trigger(MoveDirection(direction.normalize_or_zero()));
         }
     }
 }
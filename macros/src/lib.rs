@@ -3,39 +3,196 @@ extern crate proc_macro;
 use proc_macro::TokenStream;
 
 use quote::quote;
-use syn::DeriveInput;
-use syn::{parse_macro_input, Data, DataStruct, Fields};
+use syn::{parse_macro_input, Attribute, Data, DataStruct, DeriveInput, Expr, Fields};
 
-#[proc_macro_derive(Interpolate)]
+#[proc_macro_derive(Interpolate, attributes(interpolate))]
 pub fn derive_interpolate(input: TokenStream) -> TokenStream {
-    let DeriveInput { ident, data, .. } = parse_macro_input!(input);
+    let DeriveInput {
+        ident, data, attrs, ..
+    } = parse_macro_input!(input);
 
-    let body = match data {
+    let with_distance = match parse_distance(&attrs) {
+        Ok(with_distance) => with_distance,
+        Err(e) => return e.to_compile_error().into(),
+    };
+
+    let (body, distance) = match data {
         Data::Struct(DataStruct {
                          fields: Fields::Named(fields),
                          ..
                      }) => {
             let field_name = fields.named.iter().map(|field| &field.ident);
-            quote! {
-                Self {
-                    #(
-                        #field_name: self.#field_name.lerp(other.#field_name, t),
-                    )*
-                }
-            }
+            let distance_field_name = field_name.clone();
+            (
+                quote! {
+                    Self {
+                        #(
+                            #field_name: self.#field_name.lerp(other.#field_name, t),
+                        )*
+                    }
+                },
+                quote! {
+                    (0.0_f32 #(
+                        + bevy_replicon_snap::interpolation::FieldDistance::field_distance(
+                            &self.#distance_field_name,
+                            &other.#distance_field_name,
+                        )
+                        .powi(2)
+                    )*)
+                    .sqrt()
+                },
+            )
         }
         Data::Struct(DataStruct {
                          fields: Fields::Unnamed(_),
                          ..
-                     }) => quote! { Self(self.0.lerp(other.0, t)) },
+                     }) => (
+            quote! { Self(self.0.lerp(other.0, t)) },
+            quote! {
+                bevy_replicon_snap::interpolation::FieldDistance::field_distance(&self.0, &other.0)
+            },
+        ),
         _ => panic!("expected a struct"),
     };
+    let distance = with_distance.then(|| {
+        quote! {
+            impl bevy_replicon_snap::prediction::PredictionDistance for #ident {
+                fn prediction_distance(&self, other: &Self) -> f32 {
+                  #distance
+                }
+            }
+        }
+    });
     let output = quote! {
         impl bevy_replicon_snap::interpolation::Interpolate for #ident {
             fn interpolate(&self, other: Self, t: f32) -> Self {
               #body
             }
         }
+
+        #distance
+    };
+    output.into()
+}
+
+/// Whether `#[interpolate(distance)]` asks for a distance built from `FieldDistance` of each field.
+fn parse_distance(attrs: &[Attribute]) -> syn::Result<bool> {
+    let mut with_distance = false;
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("interpolate")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("distance") {
+                with_distance = true;
+                Ok(())
+            } else {
+                Err(meta.error("expected `distance`"))
+            }
+        })?;
+    }
+    Ok(with_distance)
+}
+
+#[proc_macro_derive(Quantize, attributes(quantize))]
+pub fn derive_quantize(input: TokenStream) -> TokenStream {
+    let DeriveInput {
+        ident, data, attrs, ..
+    } = parse_macro_input!(input);
+
+    let default_range = match parse_range(&attrs) {
+        Ok(range) => range,
+        Err(e) => return e.to_compile_error().into(),
+    };
+
+    let fields = match data {
+        Data::Struct(DataStruct { fields, .. }) => fields,
+        _ => panic!("expected a struct"),
+    };
+
+    let mut writes = Vec::new();
+    let mut reads = Vec::new();
+    for (index, field) in fields.iter().enumerate() {
+        let range = match parse_range(&field.attrs) {
+            Ok(range) => range.or_else(|| default_range.clone()),
+            Err(e) => return e.to_compile_error().into(),
+        };
+        let Some(range) = range else {
+            return syn::Error::new_spanned(
+                field,
+                "expected a `#[quantize(min = .., max = .., bits = ..)]` attribute \
+                 on the field or struct",
+            )
+            .to_compile_error()
+            .into();
+        };
+        let fixed = quote! { bevy_replicon_snap::quantize::FixedPoint::new(#range) };
+        let ty = &field.ty;
+        let member = match &field.ident {
+            Some(ident) => quote! { #ident },
+            None => {
+                let index = syn::Index::from(index);
+                quote! { #index }
+            }
+        };
+        writes.push(quote! {
+            bevy_replicon_snap::quantize::QuantizeField::write_quantized(
+                &self.#member,
+                #fixed,
+                message,
+            );
+        });
+        reads.push(quote! {
+            #member: <#ty as bevy_replicon_snap::quantize::QuantizeField>::read_quantized(
+                #fixed,
+                cursor,
+            )?,
+        });
+    }
+
+    let output = quote! {
+        impl bevy_replicon_snap::quantize::Quantize for #ident {
+            fn quantize(&self, message: &mut Vec<u8>) {
+                #(#writes)*
+            }
+
+            fn dequantize(
+                cursor: &mut bevy_replicon_snap::quantize::Bytes,
+            ) -> bevy::ecs::error::Result<Self> {
+                Ok(Self {
+                    #(#reads)*
+                })
+            }
+        }
     };
     output.into()
 }
+
+/// Parses `#[quantize(min = .., max = .., bits = ..)]` into the arguments of `FixedPoint::new`.
+fn parse_range(attrs: &[Attribute]) -> syn::Result<Option<proc_macro2::TokenStream>> {
+    let Some(attr) = attrs.iter().find(|attr| attr.path().is_ident("quantize")) else {
+        return Ok(None);
+    };
+
+    let mut min: Option<Expr> = None;
+    let mut max: Option<Expr> = None;
+    let mut bits: Option<Expr> = None;
+    attr.parse_nested_meta(|meta| {
+        let value = meta.value()?.parse::<Expr>()?;
+        if meta.path.is_ident("min") {
+            min = Some(value);
+        } else if meta.path.is_ident("max") {
+            max = Some(value);
+        } else if meta.path.is_ident("bits") {
+            bits = Some(value);
+        } else {
+            return Err(meta.error("expected `min`, `max` or `bits`"));
+        }
+        Ok(())
+    })?;
+
+    match (min, max, bits) {
+        (Some(min), Some(max), Some(bits)) => Ok(Some(quote! { #min, #max, #bits })),
+        _ => Err(syn::Error::new_spanned(
+            attr,
+            "expected `min`, `max` and `bits` to be set",
+        )),
+    }
+}
@@ -0,0 +1,162 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy_replicon::{client::ServerUpdateTick, prelude::*};
+
+use crate::{
+    dilation::TimeDilation, interpolation::SnapshotInterpolationConfig, prediction::Predicted,
+    spectator::SpectatorClock, Interpolated,
+};
+
+/// Drives `Time<Interpolated>` and `Time<Predicted>` along the server timeline.
+///
+/// `Time<Interpolated>` is the server time that is currently displayed for interpolated
+/// entities, `Time<Predicted>` the server time that is being predicted.
+/// Both start at server tick zero, so `elapsed_secs_f64() * max_tick_rate` is a server tick.
+/// Instead of jumping, the clocks run slightly faster or slower until they reach the
+/// timeline again.
+#[derive(Resource, Debug)]
+pub struct NetworkClocks {
+    /// Current speed of `Time<Interpolated>` relative to real time.
+    pub interpolated_speed: f32,
+    /// Current speed of `Time<Predicted>` relative to real time.
+    pub predicted_speed: f32,
+    /// Largest speed change used to catch up with the timeline, `0.1` is ±10%.
+    pub max_speed_adjustment: f32,
+    /// Seconds a clock may be off before it jumps to the timeline instead.
+    pub snap_threshold: f32,
+    /// Server ticks inputs should arrive ahead of the server without a `TimeDilation`.
+    ///
+    /// `Time<Predicted>` then runs a round trip plus this lead ahead of the latest update.
+    pub input_lead: f32,
+    update_tick: u32,
+    since_update: f32,
+}
+
+impl Default for NetworkClocks {
+    fn default() -> Self {
+        Self {
+            interpolated_speed: 1.0,
+            predicted_speed: 1.0,
+            max_speed_adjustment: 0.1,
+            snap_threshold: 0.5,
+            input_lead: 2.0,
+            update_tick: 0,
+            since_update: 0.0,
+        }
+    }
+}
+
+impl NetworkClocks {
+    /// Speed at which a clock that is `error` seconds behind its target catches up.
+    fn catch_up_speed(&self, error: f32) -> f32 {
+        1.0 + error.clamp(-self.max_speed_adjustment, self.max_speed_adjustment)
+    }
+}
+
+/// Advances `Time<Interpolated>` and `Time<Predicted>`.
+pub fn network_clock_system(
+    mut clocks: ResMut<NetworkClocks>,
+    mut interpolated_time: ResMut<Time<Interpolated>>,
+    mut predicted_time: ResMut<Time<Predicted>>,
+    update_tick: Res<ServerUpdateTick>,
+    client: Res<RepliconClient>,
+    config: Res<SnapshotInterpolationConfig>,
+    spectator: Option<Res<SpectatorClock>>,
+    dilation: Option<ResMut<TimeDilation>>,
+    time: Res<Time>,
+) {
+    let delta = time.delta_secs();
+    if update_tick.get() != clocks.update_tick {
+        clocks.update_tick = update_tick.get();
+        clocks.since_update = 0.0;
+    } else {
+        clocks.since_update += delta;
+    }
+
+    let tick_duration = 1.0 / config.max_tick_rate as f32;
+    let latest = clocks.update_tick as f32 * tick_duration;
+    clocks.interpolated_speed = match spectator {
+        // Spectators control the displayed time directly
+        Some(spectator) => {
+            jump_clock(&mut *interpolated_time, spectator.tick as f32 * tick_duration, delta);
+            if spectator.paused { 0.0 } else { spectator.speed }
+        }
+        None => {
+            // Interpolation moves from the previous towards the latest update
+            let target = latest - tick_duration + clocks.since_update.min(tick_duration);
+            advance_clock(&clocks, &mut *interpolated_time, target, delta)
+        }
+    };
+    clocks.predicted_speed = match dilation {
+        // The server feedback decides how far ahead the predicted timeline runs
+        Some(mut dilation) if dilation.pending_jump != 0.0 => {
+            // Inputs are stamped with the tick the clock is at, this one was the latest before
+            let stamped = predicted_time.elapsed_secs_f64() * config.max_tick_rate as f64;
+            dilation.jumped_from = stamped as u32;
+            let target = predicted_time.elapsed_secs() + dilation.pending_jump * tick_duration;
+            jump_clock(&mut *predicted_time, target, delta);
+            dilation.pending_jump = 0.0;
+            1.0
+        }
+        Some(dilation) => {
+            predicted_time.advance_by(Duration::from_secs_f32(delta * dilation.factor));
+            dilation.factor
+        }
+        None => {
+            // Inputs stamped now reach the server a round trip after the latest update was sent
+            let lead = client.stats().rtt as f32 + clocks.input_lead * tick_duration;
+            let target = latest + clocks.since_update + lead;
+            advance_clock(&clocks, &mut *predicted_time, target, delta)
+        }
+    };
+}
+
+/// Moves a clock towards `target` and returns the speed it was advanced with.
+fn advance_clock<T: Default>(
+    clocks: &NetworkClocks,
+    time: &mut Time<T>,
+    target: f32,
+    delta: f32,
+) -> f32 {
+    let error = target.max(0.0) - time.elapsed_secs();
+    if error.abs() > clocks.snap_threshold {
+        jump_clock(time, target, delta);
+        return 1.0;
+    }
+
+    let speed = clocks.catch_up_speed(error);
+    time.advance_by(Duration::from_secs_f32(delta * speed));
+    speed
+}
+
+/// Sets a clock to `target`, which may be in its past.
+///
+/// The clock still reports the frame `delta`, inputs predicted this frame are stepped with it.
+fn jump_clock<T: Default>(time: &mut Time<T>, target: f32, delta: f32) {
+    let target = Duration::from_secs_f32(target.max(0.0));
+    let delta = Duration::from_secs_f32(delta.max(0.0)).min(target);
+    if target - delta < time.elapsed() {
+        *time = Time::default();
+    }
+    time.advance_to(target - delta);
+    time.advance_by(delta);
+}
+
+/// Resets the clocks after disconnecting.
+pub fn network_clock_reset_system(
+    mut clocks: ResMut<NetworkClocks>,
+    mut interpolated_time: ResMut<Time<Interpolated>>,
+    mut predicted_time: ResMut<Time<Predicted>>,
+    dilation: Option<ResMut<TimeDilation>>,
+) {
+    if let Some(mut dilation) = dilation {
+        dilation.factor = 1.0;
+        dilation.pending_jump = 0.0;
+        dilation.jumped_from = 0;
+    }
+    clocks.update_tick = 0;
+    clocks.since_update = 0.0;
+    *interpolated_time = Time::default();
+    *predicted_time = Time::default();
+}
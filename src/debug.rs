@@ -0,0 +1,141 @@
+use bevy::{color::palettes::css, prelude::*};
+use bevy_replicon::prelude::*;
+
+use crate::{
+    interpolation::{Interpolate, SnapshotBuffer},
+    prediction::Predicted,
+    Interpolated,
+};
+
+/// Position of a component used to draw debug gizmos.
+pub trait DebugPosition {
+    fn debug_position(&self) -> Vec3;
+}
+
+/// Draws buffered snapshots, interpolation targets and server ghosts of predicted entities.
+///
+/// Components are added with `AppDebugGizmosExt::debug_snapshots`.
+pub struct SnapDebugGizmosPlugin;
+
+/// Runtime settings of the snapshot debug gizmos.
+#[derive(Resource, Debug)]
+pub struct SnapDebugGizmos {
+    pub enabled: bool,
+    pub radius: f32,
+    /// Buffered snapshots.
+    pub snapshot_color: Color,
+    /// Snapshot an interpolated entity is currently moving towards.
+    pub target_color: Color,
+    /// Latest confirmed server value of a predicted entity.
+    pub ghost_color: Color,
+    /// Current predicted value.
+    pub predicted_color: Color,
+}
+
+impl Default for SnapDebugGizmos {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            radius: 4.0,
+            snapshot_color: css::GRAY.into(),
+            target_color: css::YELLOW.into(),
+            ghost_color: css::RED.into(),
+            predicted_color: css::LIME.into(),
+        }
+    }
+}
+
+impl Plugin for SnapDebugGizmosPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SnapDebugGizmos>();
+    }
+}
+
+/// Draws the snapshot buffers of interpolated entities.
+pub fn interpolated_gizmos_system<C: Component + Interpolate + Clone + DebugPosition>(
+    q: Query<&SnapshotBuffer<C>, (With<Interpolated>, Without<Predicted>)>,
+    settings: Res<SnapDebugGizmos>,
+    mut gizmos: Gizmos,
+) {
+    for snapshot_buffer in q.iter() {
+        draw_snapshots(&mut gizmos, &settings, snapshot_buffer);
+        // Interpolation always moves towards the latest snapshot
+        if let Some(target) = snapshot_buffer.buffer.back() {
+            gizmos.sphere(
+                Isometry3d::from_translation(target.debug_position()),
+                settings.radius * 1.5,
+                settings.target_color,
+            );
+        }
+    }
+}
+
+/// Draws the server ghost next to the predicted value of predicted entities.
+pub fn predicted_gizmos_system<C: Component + Interpolate + Clone + DebugPosition>(
+    q: Query<(&C, &SnapshotBuffer<C>), (With<Predicted>, Without<Interpolated>)>,
+    settings: Res<SnapDebugGizmos>,
+    mut gizmos: Gizmos,
+) {
+    for (component, snapshot_buffer) in q.iter() {
+        draw_snapshots(&mut gizmos, &settings, snapshot_buffer);
+        let predicted = component.debug_position();
+        gizmos.sphere(
+            Isometry3d::from_translation(predicted),
+            settings.radius * 1.5,
+            settings.predicted_color,
+        );
+        if let Some(ghost) = snapshot_buffer.buffer.back() {
+            let ghost = ghost.debug_position();
+            gizmos.sphere(
+                Isometry3d::from_translation(ghost),
+                settings.radius * 1.5,
+                settings.ghost_color,
+            );
+            gizmos.line(ghost, predicted, settings.ghost_color);
+        }
+    }
+}
+
+fn draw_snapshots<C: Component + Interpolate + Clone + DebugPosition>(
+    gizmos: &mut Gizmos,
+    settings: &SnapDebugGizmos,
+    snapshot_buffer: &SnapshotBuffer<C>,
+) {
+    let mut previous = None;
+    for snapshot in snapshot_buffer.buffer.iter() {
+        let position = snapshot.debug_position();
+        gizmos.sphere(
+            Isometry3d::from_translation(position),
+            settings.radius,
+            settings.snapshot_color,
+        );
+        if let Some(previous) = previous {
+            gizmos.line(previous, position, settings.snapshot_color);
+        }
+        previous = Some(position);
+    }
+}
+
+pub trait AppDebugGizmosExt {
+    /// Draw debug gizmos for the snapshots of a component registered for interpolation.
+    /// Requires the `SnapDebugGizmosPlugin`.
+    fn debug_snapshots<C>(&mut self) -> &mut Self
+    where
+        C: Component + Interpolate + Clone + DebugPosition;
+}
+
+impl AppDebugGizmosExt for App {
+    fn debug_snapshots<C>(&mut self) -> &mut Self
+    where
+        C: Component + Interpolate + Clone + DebugPosition,
+    {
+        self.add_systems(
+            PostUpdate,
+            (
+                interpolated_gizmos_system::<C>,
+                predicted_gizmos_system::<C>,
+            )
+                .run_if(client_connected.and(|settings: Res<SnapDebugGizmos>| settings.enabled)),
+        )
+    }
+}
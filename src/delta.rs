@@ -0,0 +1,600 @@
+use std::{collections::VecDeque, marker::PhantomData};
+
+use bevy::{
+    ecs::{
+        component::Mutable,
+        entity::{EntityMapper, MapEntities},
+    },
+    platform::collections::HashMap,
+    prelude::*,
+};
+use bevy_replicon::{
+    prelude::*,
+    shared::replication::{
+        command_markers::MarkerConfig,
+        deferred_entity::DeferredEntity,
+        replication_registry::{
+            ctx::{RemoveCtx, WriteCtx},
+            rule_fns::RuleFns,
+        },
+    },
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::interpolation::{insert_snapshot, Interpolate, RecordSnapshotsMarker, SnapshotBuffer};
+
+/// This trait defines how a component is encoded relative to a previous value
+/// and is required for delta compression.
+pub trait Diff: Sized {
+    type Delta: Serialize + DeserializeOwned + Send + Sync + 'static;
+
+    fn diff(&self, baseline: &Self) -> Self::Delta;
+    fn apply_diff(baseline: &Self, delta: &Self::Delta) -> Self;
+}
+
+/// Replicated in place of a delta compressed component.
+#[derive(Component, Deserialize, Serialize)]
+#[serde(bound(
+    serialize = "C: Serialize, C::Delta: Serialize",
+    deserialize = "C: DeserializeOwned, C::Delta: DeserializeOwned"
+))]
+pub enum DeltaEncoded<C: Diff + Send + Sync + 'static> {
+    Full {
+        tick: u32,
+        value: C,
+    },
+    Delta {
+        tick: u32,
+        baseline_tick: u32,
+        delta: C::Delta,
+    },
+}
+
+/// Recent values of a delta compressed component with the server tick they were recorded at.
+///
+/// Kept on the server to encode against and on the client to decode against.
+#[derive(Component)]
+pub struct DeltaHistory<C: Component> {
+    pub values: VecDeque<(u32, C)>,
+    pub last_full_tick: Option<u32>,
+    /// Set on the client while it waits for the full value it requested.
+    pub keyframe_requested: bool,
+}
+
+impl<C: Component + Clone> DeltaHistory<C> {
+    pub fn new() -> Self {
+        Self {
+            values: VecDeque::new(),
+            last_full_tick: None,
+            keyframe_requested: false,
+        }
+    }
+
+    pub fn insert(&mut self, tick: u32, value: C, max_len: usize) {
+        while self.values.len() >= max_len.max(1) {
+            self.values.pop_front();
+        }
+        self.values.push_back((tick, value));
+    }
+
+    /// The value recorded exactly at `tick`.
+    pub fn get(&self, tick: u32) -> Option<&C> {
+        self.values
+            .iter()
+            .find(|(value_tick, _)| *value_tick == tick)
+            .map(|(_, value)| value)
+    }
+
+    /// The latest value that was recorded at or before `tick`.
+    pub fn baseline(&self, tick: u32) -> Option<&(u32, C)> {
+        self.values
+            .iter()
+            .rev()
+            .find(|(value_tick, _)| *value_tick <= tick)
+    }
+}
+
+/// Upper bound of values a client keeps to decode against.
+const CLIENT_HISTORY_LEN: usize = 64;
+
+/// Number of ticks before the latest one a client can acknowledge in one `DeltaAck`.
+const ACK_MASK_LEN: u32 = 32;
+
+/// Sent by clients to acknowledge the ticks of `C` they hold a value for, per entity.
+///
+/// Bit `d - 1` of the mask is set if the value at `latest - d` is held too.
+#[derive(Event, Deserialize, Serialize, Clone, Debug)]
+pub struct DeltaAck<C> {
+    pub acks: Vec<(Entity, u32, u32)>,
+    #[serde(skip)]
+    marker: PhantomData<C>,
+}
+
+impl<C> MapEntities for DeltaAck<C> {
+    fn map_entities<M: EntityMapper>(&mut self, entity_mapper: &mut M) {
+        for (entity, ..) in self.acks.iter_mut() {
+            *entity = entity_mapper.get_mapped(*entity);
+        }
+    }
+}
+
+/// Sent by a client that received a delta against a baseline it doesn't hold.
+#[derive(Event, Deserialize, Serialize, Clone, Debug)]
+pub struct DeltaKeyframeRequest<C> {
+    pub entity: Entity,
+    #[serde(skip)]
+    marker: PhantomData<C>,
+}
+
+impl<C> MapEntities for DeltaKeyframeRequest<C> {
+    fn map_entities<M: EntityMapper>(&mut self, entity_mapper: &mut M) {
+        self.entity = entity_mapper.get_mapped(self.entity);
+    }
+}
+
+/// Latest acknowledgement of each client for this entity, kept on the server.
+#[derive(Component)]
+pub struct DeltaAcks<C> {
+    pub clients: HashMap<Entity, (u32, u32)>,
+    marker: PhantomData<C>,
+}
+
+impl<C> Default for DeltaAcks<C> {
+    fn default() -> Self {
+        Self {
+            clients: HashMap::default(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<C> DeltaAcks<C> {
+    /// Whether `client` has acknowledged holding the value at `tick`.
+    pub fn holds(&self, client: Entity, tick: u32) -> bool {
+        let Some(&(latest, mask)) = self.clients.get(&client) else {
+            return false;
+        };
+        match latest.checked_sub(tick) {
+            Some(0) => true,
+            Some(distance) if distance <= ACK_MASK_LEN => mask & (1 << (distance - 1)) != 0,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Resource, Serialize, Deserialize, Debug)]
+pub struct DeltaCompressionConfig {
+    /// Number of values the server keeps to encode against.
+    pub history_len: usize,
+    /// Ticks after which a full value is sent, so clients that missed a baseline can recover.
+    pub keyframe_interval: u32,
+}
+
+impl Default for DeltaCompressionConfig {
+    fn default() -> Self {
+        Self {
+            history_len: 32,
+            keyframe_interval: 30,
+        }
+    }
+}
+
+/// Encodes changed components against the latest value every client holds.
+///
+/// Replicon serializes a component once for all clients, so the baseline is shared:
+/// a client that lags behind on acknowledgements makes everyone receive full values.
+pub fn delta_encode_system<C: Component + Diff + Clone>(
+    mut q: Query<
+        (
+            Entity,
+            &C,
+            Option<&mut DeltaHistory<C>>,
+            Option<&mut DeltaAcks<C>>,
+        ),
+        Changed<C>,
+    >,
+    q_clients: Query<Entity, With<AuthorizedClient>>,
+    server_tick: Res<ServerTick>,
+    config: Res<DeltaCompressionConfig>,
+    mut commands: Commands,
+) {
+    let tick = server_tick.get();
+    for (e, component, history, acks) in q.iter_mut() {
+        let mut entity = commands.entity(e);
+        let acked = acks.and_then(|mut acks| {
+            acks.clients.retain(|client, _| q_clients.contains(*client));
+            if q_clients.is_empty() {
+                return None;
+            }
+            // A baseline is only usable if every client holds it
+            history
+                .as_deref()?
+                .values
+                .iter()
+                .rev()
+                .map(|(value_tick, _)| *value_tick)
+                .find(|value_tick| q_clients.iter().all(|client| acks.holds(client, *value_tick)))
+        });
+
+        if let Some(mut history) = history {
+            let encoded = encode_delta(&mut history, component, tick, acked, &config);
+            entity.insert(encoded);
+        } else {
+            let mut history = DeltaHistory::new();
+            let encoded = encode_delta(&mut history, component, tick, acked, &config);
+            entity.insert((encoded, history, DeltaAcks::<C>::default()));
+        }
+    }
+}
+
+fn encode_delta<C: Component + Diff + Clone>(
+    history: &mut DeltaHistory<C>,
+    component: &C,
+    tick: u32,
+    acked: Option<u32>,
+    config: &DeltaCompressionConfig,
+) -> DeltaEncoded<C> {
+    let keyframe_due = history
+        .last_full_tick
+        .is_none_or(|last| tick.saturating_sub(last) >= config.keyframe_interval);
+    let baseline = acked.and_then(|acked| history.baseline(acked));
+    let encoded = match baseline {
+        Some((baseline_tick, baseline)) if !keyframe_due => DeltaEncoded::Delta {
+            tick,
+            baseline_tick: *baseline_tick,
+            delta: component.diff(baseline),
+        },
+        _ => {
+            history.last_full_tick = Some(tick);
+            DeltaEncoded::Full {
+                tick,
+                value: component.clone(),
+            }
+        }
+    };
+    history.insert(tick, component.clone(), config.history_len);
+    encoded
+}
+
+/// Stores the ticks a client acknowledged on each entity, keeping the newest acknowledgement.
+pub fn delta_ack_receive_system<C: Component>(
+    trigger: Trigger<FromClient<DeltaAck<C>>>,
+    mut q: Query<&mut DeltaAcks<C>>,
+) {
+    let client = trigger.client_entity;
+    for &(e, latest, mask) in trigger.event().acks.iter() {
+        let Ok(mut acks) = q.get_mut(e) else {
+            continue;
+        };
+        // Acknowledgements are unreliable and may arrive out of order
+        if acks
+            .clients
+            .get(&client)
+            .is_none_or(|&(acked, _)| acked <= latest)
+        {
+            acks.clients.insert(client, (latest, mask));
+        }
+    }
+}
+
+/// Sends a full value of `C` with the next update after a client lost its baseline.
+pub fn delta_keyframe_request_system<C: Component<Mutability=Mutable>>(
+    trigger: Trigger<FromClient<DeltaKeyframeRequest<C>>>,
+    mut q: Query<(&mut C, &mut DeltaHistory<C>)>,
+) {
+    if let Ok((mut component, mut history)) = q.get_mut(trigger.event().entity) {
+        history.last_full_tick = None;
+        component.set_changed();
+    }
+}
+
+/// Acknowledges the ticks of `C` the client holds a value for.
+pub fn delta_ack_send_system<C: Component>(
+    q: Query<(Entity, &DeltaHistory<C>), Changed<DeltaHistory<C>>>,
+    mut commands: Commands,
+) {
+    let acks: Vec<_> = q
+        .iter()
+        .filter_map(|(e, history)| {
+            let &(latest, _) = history.values.back()?;
+            let mask = history
+                .values
+                .iter()
+                .filter_map(|(tick, _)| latest.checked_sub(*tick))
+                .filter(|distance| (1..=ACK_MASK_LEN).contains(distance))
+                .fold(0u32, |mask, distance| mask | 1 << (distance - 1));
+            Some((e, latest, mask))
+        })
+        .collect();
+    if acks.is_empty() {
+        return;
+    }
+    commands.client_trigger(DeltaAck::<C> {
+        acks,
+        marker: PhantomData,
+    });
+}
+
+/// Rebuilds the full value from the buffered baseline and records it as a snapshot.
+///
+/// Deltas whose baseline is missing are dropped and a full value is requested instead of waiting
+/// for the next keyframe.
+pub fn write_delta_component<
+    C: Clone + Interpolate + Component<Mutability=Mutable> + Diff,
+>(
+    ctx: &mut WriteCtx,
+    rule_fns: &RuleFns<DeltaEncoded<C>>,
+    entity: &mut DeferredEntity,
+    cursor: &mut bevy_replicon::bytes::Bytes,
+) -> Result<()> {
+    let encoded = rule_fns.deserialize(ctx, cursor)?;
+    let (tick, component) = match encoded {
+        DeltaEncoded::Full { tick, value } => {
+            if let Some(mut history) = entity.get_mut::<DeltaHistory<C>>() {
+                history.keyframe_requested = false;
+            }
+            (tick, value)
+        }
+        DeltaEncoded::Delta {
+            tick,
+            baseline_tick,
+            delta,
+        } => {
+            let Some(component) = entity
+                .get::<DeltaHistory<C>>()
+                .and_then(|history| history.get(baseline_tick))
+                .map(|baseline| C::apply_diff(baseline, &delta))
+            else {
+                request_keyframe::<C>(ctx, entity);
+                return Ok(());
+            };
+            if let Some(mut history) = entity.get_mut::<DeltaHistory<C>>() {
+                // The server encodes against values every client holds, never older than this one
+                history.values.retain(|(value_tick, _)| *value_tick >= baseline_tick);
+            }
+            (tick, component)
+        }
+    };
+
+    if let Some(mut history) = entity.get_mut::<DeltaHistory<C>>() {
+        history.insert(tick, component.clone(), CLIENT_HISTORY_LEN);
+    } else {
+        let mut history = DeltaHistory::new();
+        history.insert(tick, component.clone(), CLIENT_HISTORY_LEN);
+        ctx.commands.entity(entity.id()).insert(history);
+    }
+
+    if entity.contains::<RecordSnapshotsMarker>() {
+        insert_snapshot(ctx, entity, component);
+    } else if let Some(mut current) = entity.get_mut::<C>() {
+        *current = component;
+    } else {
+        entity.insert(component);
+    }
+
+    Ok(())
+}
+
+/// Asks the server for a full value, once until it arrives.
+fn request_keyframe<C: Component + Clone>(ctx: &mut WriteCtx, entity: &mut DeferredEntity) {
+    match entity.get_mut::<DeltaHistory<C>>() {
+        Some(history) if history.keyframe_requested => return,
+        Some(mut history) => history.keyframe_requested = true,
+        None => {
+            let mut history = DeltaHistory::<C>::new();
+            history.keyframe_requested = true;
+            ctx.commands.entity(entity.id()).insert(history);
+        }
+    }
+    ctx.commands.client_trigger(DeltaKeyframeRequest::<C> {
+        entity: entity.id(),
+        marker: PhantomData,
+    });
+}
+
+pub fn remove_delta_component<C: Clone + Interpolate + Component + Diff>(
+    ctx: &mut RemoveCtx,
+    entity: &mut DeferredEntity,
+) {
+    ctx.commands
+        .entity(entity.id())
+        .remove::<DeltaEncoded<C>>()
+        .remove::<DeltaHistory<C>>()
+        .remove::<DeltaAcks<C>>()
+        .remove::<SnapshotBuffer<C>>()
+        .remove::<C>();
+}
+
+/// Registers `C` to be replicated as `DeltaEncoded<C>`.
+pub(crate) fn register_delta_compression<C>(app: &mut App) -> &mut App
+where
+    C: Component<Mutability=Mutable> + Interpolate + Diff + Clone + Serialize + DeserializeOwned,
+{
+    app.init_resource::<DeltaCompressionConfig>()
+        .add_mapped_client_trigger::<DeltaAck<C>>(Channel::Unreliable)
+        .add_mapped_client_trigger::<DeltaKeyframeRequest<C>>(Channel::Ordered)
+        .add_observer(delta_ack_receive_system::<C>)
+        .add_observer(delta_keyframe_request_system::<C>)
+        .add_systems(
+            PreUpdate,
+            delta_ack_send_system::<C>
+                .after(ClientSet::Receive)
+                .run_if(client_connected),
+        )
+        .add_systems(
+            PostUpdate,
+            delta_encode_system::<C>
+                .before(ServerSet::Send)
+                .run_if(server_running),
+        )
+        .replicate::<DeltaEncoded<C>>()
+        .register_marker_with::<RecordSnapshotsMarker>(MarkerConfig {
+            need_history: true,
+            ..default()
+        })
+        .set_command_fns(write_delta_component::<C>, remove_delta_component::<C>)
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_replicon::test_app::ServerTestAppExt;
+
+    use super::*;
+    use crate::{interpolation::AppInterpolationExt, test_utils::connected_apps};
+
+    #[derive(Component, Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+    struct Position(f32);
+
+    impl Interpolate for Position {
+        fn interpolate(&self, other: Self, t: f32) -> Self {
+            Self(self.0 + (other.0 - self.0) * t)
+        }
+    }
+
+    impl Diff for Position {
+        type Delta = f32;
+
+        fn diff(&self, baseline: &Self) -> f32 {
+            self.0 - baseline.0
+        }
+
+        fn apply_diff(baseline: &Self, delta: &f32) -> Self {
+            Self(baseline.0 + delta)
+        }
+    }
+
+    #[test]
+    fn acks_cover_masked_ticks() {
+        let client = Entity::from_raw(1);
+        let mut acks = DeltaAcks::<Position>::default();
+        acks.clients.insert(client, (10, 0b101));
+
+        assert!(acks.holds(client, 10));
+        assert!(acks.holds(client, 9));
+        assert!(!acks.holds(client, 8));
+        assert!(acks.holds(client, 7));
+        assert!(!acks.holds(client, 11));
+        assert!(!acks.holds(Entity::from_raw(2), 10));
+    }
+
+    #[test]
+    fn encodes_against_acked_baseline() {
+        let config = DeltaCompressionConfig::default();
+        let mut server = DeltaHistory::new();
+        let mut client = DeltaHistory::new();
+
+        let encoded = encode_delta(&mut server, &Position(1.0), 1, None, &config);
+        let DeltaEncoded::Full { tick, value } = encoded else {
+            panic!("first value should be sent in full");
+        };
+        client.insert(tick, value, CLIENT_HISTORY_LEN);
+
+        let encoded = encode_delta(&mut server, &Position(3.5), 2, Some(1), &config);
+        let DeltaEncoded::Delta {
+            tick,
+            baseline_tick,
+            delta,
+        } = encoded
+        else {
+            panic!("acked baseline should be used");
+        };
+        assert_eq!((tick, baseline_tick), (2, 1));
+        let baseline = client.get(baseline_tick).unwrap();
+        assert_eq!(Position::apply_diff(baseline, &delta), Position(3.5));
+
+        let keyframe_tick = 1 + config.keyframe_interval;
+        let encoded = encode_delta(&mut server, &Position(4.0), keyframe_tick, Some(2), &config);
+        assert!(matches!(encoded, DeltaEncoded::Full { .. }));
+    }
+
+    fn replicated_position(app: &mut App) -> (Entity, Position) {
+        let (entity, position) = app
+            .world_mut()
+            .query::<(Entity, &Position)>()
+            .single(app.world())
+            .unwrap();
+        (entity, *position)
+    }
+
+    #[test]
+    fn acked_values_are_sent_as_deltas() {
+        let (mut server_app, mut client_app, _) = connected_apps(|app| {
+            app.replicate_interpolated_delta::<Position>();
+        });
+        let server_entity = server_app
+            .world_mut()
+            .spawn((Replicated, Position(1.0)))
+            .id();
+
+        server_app.update();
+        server_app.exchange_with_client(&mut client_app);
+        client_app.update();
+        assert_eq!(replicated_position(&mut client_app).1, Position(1.0));
+
+        // The client acknowledges the first value
+        server_app.exchange_with_client(&mut client_app);
+        server_app.world_mut().get_mut::<Position>(server_entity).unwrap().0 = 2.0;
+        server_app.update();
+        let encoded = server_app
+            .world()
+            .get::<DeltaEncoded<Position>>(server_entity)
+            .unwrap();
+        assert!(matches!(encoded, DeltaEncoded::Delta { .. }));
+
+        server_app.exchange_with_client(&mut client_app);
+        client_app.update();
+        assert_eq!(replicated_position(&mut client_app).1, Position(2.0));
+    }
+
+    #[test]
+    fn missing_baseline_requests_keyframe() {
+        let (mut server_app, mut client_app, _) = connected_apps(|app| {
+            app.replicate_interpolated_delta::<Position>();
+        });
+        let server_entity = server_app
+            .world_mut()
+            .spawn((Replicated, Position(1.0)))
+            .id();
+
+        server_app.update();
+        server_app.exchange_with_client(&mut client_app);
+        client_app.update();
+        server_app.exchange_with_client(&mut client_app);
+
+        // The client loses the baseline the server will encode against
+        let (client_entity, _) = replicated_position(&mut client_app);
+        client_app
+            .world_mut()
+            .get_mut::<DeltaHistory<Position>>(client_entity)
+            .unwrap()
+            .values
+            .clear();
+        server_app.world_mut().get_mut::<Position>(server_entity).unwrap().0 = 2.0;
+        server_app.update();
+        server_app.exchange_with_client(&mut client_app);
+        client_app.update();
+        assert_eq!(replicated_position(&mut client_app).1, Position(1.0));
+        assert!(client_app
+            .world()
+            .get::<DeltaHistory<Position>>(client_entity)
+            .unwrap()
+            .keyframe_requested);
+
+        server_app.exchange_with_client(&mut client_app);
+        server_app.update();
+        let encoded = server_app
+            .world()
+            .get::<DeltaEncoded<Position>>(server_entity)
+            .unwrap();
+        assert!(matches!(encoded, DeltaEncoded::Full { .. }));
+
+        server_app.exchange_with_client(&mut client_app);
+        client_app.update();
+        assert_eq!(replicated_position(&mut client_app).1, Position(2.0));
+        assert!(!client_app
+            .world()
+            .get::<DeltaHistory<Position>>(client_entity)
+            .unwrap()
+            .keyframe_requested);
+    }
+}
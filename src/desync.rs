@@ -0,0 +1,263 @@
+use std::{fmt::Debug, marker::PhantomData};
+
+use bevy::{
+    ecs::entity::{EntityMapper, MapEntities},
+    prelude::*,
+};
+use bevy_replicon::prelude::*;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    events::Desync,
+    interpolation::{Interpolate, SnapshotBuffer},
+    prediction::{OwnerPredicted, Predicted, PredictionHistory, PredictionMode},
+    NetworkOwner,
+};
+
+/// Checksums of the authoritative values of `C` for the entities a client predicts at a server tick.
+#[derive(Event, Deserialize, Serialize, Clone, Debug)]
+pub struct StateChecksums<C> {
+    pub tick: u32,
+    pub checksums: Vec<(Entity, u64)>,
+    #[serde(skip)]
+    marker: PhantomData<C>,
+}
+
+impl<C> MapEntities for StateChecksums<C> {
+    fn map_entities<M: EntityMapper>(&mut self, entity_mapper: &mut M) {
+        for (entity, _) in self.checksums.iter_mut() {
+            *entity = entity_mapper.get_mapped(*entity);
+        }
+    }
+}
+
+/// Enables desync detection for `C`, added by `AppDesyncExt::detect_desyncs`.
+#[derive(Resource)]
+pub struct DesyncDetection<C> {
+    /// Server tick the latest checksums were sent for.
+    pub last_tick: u32,
+    marker: PhantomData<C>,
+}
+
+impl<C> Default for DesyncDetection<C> {
+    fn default() -> Self {
+        Self {
+            last_tick: 0,
+            marker: PhantomData,
+        }
+    }
+}
+
+/// Hashes the postcard encoding of a value with FNV-1a, which is stable across platforms.
+pub fn state_checksum<C: Serialize>(value: &C) -> Option<u64> {
+    let bytes = postcard::to_allocvec(value).ok()?;
+    let hash = bytes.iter().fold(0xcbf29ce484222325, |hash: u64, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    });
+    Some(hash)
+}
+
+/// Sends every client the checksums of the entities it predicts, once per server tick.
+///
+/// Runs after the tick was sent, so the checksums describe the values replicated with it:
+/// all inputs stamped before the tick, each applied with its predicted delta time.
+pub fn server_checksum_system<C: Component + Serialize>(
+    q: Query<(
+        Entity,
+        &C,
+        Option<&PredictionMode>,
+        Has<OwnerPredicted>,
+        Option<&NetworkOwner>,
+    )>,
+    q_clients: Query<Entity, With<AuthorizedClient>>,
+    mut detection: ResMut<DesyncDetection<C>>,
+    server_tick: Res<ServerTick>,
+    mut commands: Commands,
+) {
+    let tick = server_tick.get();
+    if tick == detection.last_tick {
+        return;
+    }
+    detection.last_tick = tick;
+
+    let entities: Vec<_> = q
+        .iter()
+        .filter_map(|(e, component, mode, owner_predicted, owner)| {
+            let mode = match (mode, owner_predicted) {
+                (Some(mode), _) => *mode,
+                (None, true) => PredictionMode::Owner,
+                (None, false) => return None,
+            };
+            let hash = state_checksum(component)?;
+            Some((e, hash, mode, owner.map(|owner| owner.0)))
+        })
+        .collect();
+    for client_entity in q_clients.iter() {
+        let checksums: Vec<_> = entities
+            .iter()
+            .filter(|(.., mode, owner)| match mode {
+                PredictionMode::Owner => *owner == Some(client_entity),
+                PredictionMode::AllClients => true,
+                PredictionMode::InterpolatedOnly | PredictionMode::Authoritative => false,
+            })
+            .map(|&(e, hash, ..)| (e, hash))
+            .collect();
+        if checksums.is_empty() {
+            continue;
+        }
+        commands.server_trigger(ToClients {
+            mode: SendMode::Direct(client_entity),
+            event: StateChecksums::<C> {
+                tick,
+                checksums,
+                marker: PhantomData,
+            },
+        });
+    }
+}
+
+/// Compares the server checksums with the values predicted for the same tick.
+///
+/// Ticks outside the `PredictionHistory` are skipped.
+pub fn client_checksum_system<C: Component + Interpolate + Clone + Serialize + Debug>(
+    trigger: Trigger<StateChecksums<C>>,
+    q: Query<(&PredictionHistory<C>, Option<&SnapshotBuffer<C>>), With<Predicted>>,
+    mut commands: Commands,
+) {
+    let tick = trigger.event().tick;
+    for &(e, hash) in trigger.event().checksums.iter() {
+        let Ok((history, snapshot_buffer)) = q.get(e) else {
+            continue;
+        };
+        // Late or early checksums are outside the history, the prediction for them is unknown
+        if !history.covers(tick) {
+            continue;
+        }
+        let Some(predicted) = history.at(tick) else {
+            continue;
+        };
+        if state_checksum(predicted) == Some(hash) {
+            continue;
+        }
+
+        let server = snapshot_buffer.and_then(|snapshot_buffer| {
+            snapshot_buffer
+                .ticks
+                .iter()
+                .rposition(|snapshot_tick| *snapshot_tick <= tick)
+                .map(|index| snapshot_buffer.buffer[index].clone())
+        });
+        warn!(
+            "{e} desynced {} at tick {tick}: predicted {predicted:?}, server {server:?}",
+            std::any::type_name::<C>()
+        );
+        commands.trigger_targets(
+            Desync {
+                entity: e,
+                tick,
+                predicted: predicted.clone(),
+                server,
+            },
+            e,
+        );
+    }
+}
+
+pub trait AppDesyncExt {
+    /// Detect desyncs of a predicted component.
+    /// The server sends each client a checksum of every entity it predicts each tick and
+    /// clients trigger `Desync<C>` when their prediction for that tick differs.
+    /// Inputs have to be sent with `PredictedInputWriter`, so both sides apply them at the
+    /// same tick with the same delta time.
+    /// Must be called on both the server and the clients.
+    fn detect_desyncs<C>(&mut self) -> &mut Self
+    where
+        C: Component + Interpolate + Clone + Serialize + DeserializeOwned + Debug;
+}
+
+impl AppDesyncExt for App {
+    fn detect_desyncs<C>(&mut self) -> &mut Self
+    where
+        C: Component + Interpolate + Clone + Serialize + DeserializeOwned + Debug,
+    {
+        self.init_resource::<DesyncDetection<C>>()
+            .add_mapped_server_trigger::<StateChecksums<C>>(Channel::Unreliable)
+            .add_observer(client_checksum_system::<C>)
+            .add_systems(
+                PostUpdate,
+                server_checksum_system::<C>
+                    .after(ServerSet::Send)
+                    .run_if(server_running),
+            )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Component, Deserialize, Serialize, Clone, Debug, PartialEq)]
+    struct Health(f32);
+
+    impl Interpolate for Health {
+        fn interpolate(&self, other: Self, t: f32) -> Self {
+            Self(self.0 + (other.0 - self.0) * t)
+        }
+    }
+
+    #[derive(Resource, Default)]
+    struct Desyncs(Vec<u32>);
+
+    fn app() -> (App, Entity) {
+        let mut app = App::new();
+        app.init_resource::<Desyncs>()
+            .add_observer(client_checksum_system::<Health>)
+            .add_observer(
+                |trigger: Trigger<Desync<Health>>, mut desyncs: ResMut<Desyncs>| {
+                    desyncs.0.push(trigger.event().tick);
+                },
+            );
+
+        let mut history = PredictionHistory::default();
+        for tick in 10..15 {
+            history.insert(tick, Health(tick as f32), 0);
+        }
+        // The server confirmed tick 12, older values are dropped
+        history.insert(15, Health(15.0), 12);
+        let entity = app.world_mut().spawn((Predicted, history)).id();
+
+        (app, entity)
+    }
+
+    fn checksum(app: &mut App, entity: Entity, tick: u32, value: f32) {
+        app.world_mut().trigger(StateChecksums::<Health> {
+            tick,
+            checksums: vec![(entity, state_checksum(&Health(value)).unwrap())],
+            marker: PhantomData,
+        });
+    }
+
+    #[test]
+    fn mismatch_raises_desync() {
+        let (mut app, entity) = app();
+
+        // The value at a tick is the one predicted by the inputs before it
+        checksum(&mut app, entity, 14, 13.0);
+        assert!(app.world().resource::<Desyncs>().0.is_empty());
+
+        checksum(&mut app, entity, 14, 0.0);
+        assert_eq!(app.world().resource::<Desyncs>().0, [14]);
+    }
+
+    #[test]
+    fn checksums_outside_history_are_skipped() {
+        let (mut app, entity) = app();
+
+        // A late checksum for a tick whose prediction was already dropped
+        checksum(&mut app, entity, 11, 0.0);
+        // A checksum for a tick the client has not predicted yet
+        checksum(&mut app, entity, 20, 0.0);
+
+        assert!(app.world().resource::<Desyncs>().0.is_empty());
+    }
+}
@@ -0,0 +1,205 @@
+use std::marker::PhantomData;
+
+use bevy::{
+    diagnostic::{Diagnostic, DiagnosticPath, Diagnostics, RegisterDiagnostic},
+    prelude::*,
+};
+use bevy_replicon::prelude::*;
+
+use crate::{
+    interpolation::{Interpolate, SnapshotBuffer, SnapshotInterpolationConfig},
+    prediction::{Predicted, PredictionTolerance},
+    profile::{InterpolationProfileName, InterpolationProfiles},
+    Interpolated,
+};
+
+/// Diagnostic paths of a component registered for interpolation.
+///
+/// All paths are prefixed with `snap/<component name>/`.
+#[derive(Resource)]
+pub struct SnapDiagnostics<C> {
+    /// Average number of buffered snapshots.
+    pub buffer_depth: DiagnosticPath,
+    /// Average seconds since the latest snapshot.
+    pub snapshot_age: DiagnosticPath,
+    /// Fraction of interpolated entities that are frozen at the latest snapshot.
+    pub interpolation_frozen: DiagnosticPath,
+    /// Average seconds the displayed tick is ahead of the latest snapshot.
+    pub extrapolation_time: DiagnosticPath,
+    /// Number of server corrections that changed a predicted value.
+    pub mispredictions: DiagnosticPath,
+    /// Average distance between the predicted and the corrected value.
+    pub correction_avg: DiagnosticPath,
+    /// Largest distance between the predicted and the corrected value.
+    pub correction_max: DiagnosticPath,
+    marker: PhantomData<C>,
+}
+
+impl<C> SnapDiagnostics<C> {
+    pub fn new() -> Self {
+        let name = std::any::type_name::<C>()
+            .split('<')
+            .next()
+            .and_then(|path| path.rsplit("::").next())
+            .unwrap_or("component");
+        let path = |metric: &str| DiagnosticPath::new(format!("snap/{name}/{metric}"));
+        Self {
+            buffer_depth: path("buffer_depth"),
+            snapshot_age: path("snapshot_age"),
+            interpolation_frozen: path("interpolation_frozen"),
+            extrapolation_time: path("extrapolation_time"),
+            mispredictions: path("mispredictions"),
+            correction_avg: path("correction_avg"),
+            correction_max: path("correction_max"),
+            marker: PhantomData,
+        }
+    }
+}
+
+/// Corrections recorded by the prediction systems since the last diagnostics update.
+#[derive(Resource)]
+pub struct PredictionStats<C> {
+    pub mispredictions: u32,
+    pub corrections: u32,
+    pub correction_sum: f32,
+    pub correction_max: f32,
+    marker: PhantomData<C>,
+}
+
+impl<C> Default for PredictionStats<C> {
+    fn default() -> Self {
+        Self {
+            mispredictions: 0,
+            corrections: 0,
+            correction_sum: 0.0,
+            correction_max: 0.0,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<C> PredictionStats<C> {
+    pub fn record_correction(&mut self, distance: f32) {
+        self.corrections += 1;
+        if distance > 0.0 {
+            self.mispredictions += 1;
+        }
+        self.correction_sum += distance;
+        self.correction_max = self.correction_max.max(distance);
+    }
+}
+
+/// Measures snapshot buffers of interpolated and predicted entities.
+///
+/// Interpolated entities count as frozen once the tick they display, including the profile delay,
+/// passes their latest snapshot.
+pub fn snapshot_diagnostics_system<C: Component + Interpolate + Clone>(
+    q: Query<
+        (
+            &SnapshotBuffer<C>,
+            Has<Interpolated>,
+            Option<&InterpolationProfileName>,
+        ),
+        Or<(With<Interpolated>, With<Predicted>)>,
+    >,
+    paths: Res<SnapDiagnostics<C>>,
+    config: Res<SnapshotInterpolationConfig>,
+    profiles: Res<InterpolationProfiles>,
+    mut diagnostics: Diagnostics,
+) {
+    let tick_duration = 1.0 / (config.max_tick_rate as f32);
+    let mut count = 0;
+    let mut interpolated = 0;
+    let mut depth = 0;
+    let mut age = 0.0;
+    let mut frozen = 0;
+    let mut extrapolation = 0.0;
+    for (snapshot_buffer, is_interpolated, profile) in q.iter() {
+        count += 1;
+        depth += snapshot_buffer.buffer.len();
+        age += snapshot_buffer.age();
+        if is_interpolated {
+            interpolated += 1;
+            let render_tick = snapshot_buffer.render_tick(profiles.get(profile), tick_duration);
+            let overshoot = (render_tick - snapshot_buffer.latest_snapshot_tick() as f64) as f32
+                * tick_duration;
+            if overshoot > 0.0 {
+                frozen += 1;
+                extrapolation += overshoot;
+            }
+        }
+    }
+    if count == 0 {
+        return;
+    }
+
+    diagnostics.add_measurement(&paths.buffer_depth, || depth as f64 / count as f64);
+    diagnostics.add_measurement(&paths.snapshot_age, || age as f64 / count as f64);
+    if interpolated > 0 {
+        diagnostics.add_measurement(&paths.interpolation_frozen, || {
+            frozen as f64 / interpolated as f64
+        });
+        diagnostics.add_measurement(&paths.extrapolation_time, || {
+            extrapolation as f64 / interpolated as f64
+        });
+    }
+}
+
+/// Publishes and resets the corrections recorded by the prediction systems.
+pub fn prediction_diagnostics_system<C: Component>(
+    mut stats: ResMut<PredictionStats<C>>,
+    paths: Res<SnapDiagnostics<C>>,
+    mut diagnostics: Diagnostics,
+) {
+    diagnostics.add_measurement(&paths.mispredictions, || stats.mispredictions as f64);
+    if stats.corrections > 0 {
+        diagnostics.add_measurement(&paths.correction_avg, || {
+            stats.correction_sum as f64 / stats.corrections as f64
+        });
+        diagnostics.add_measurement(&paths.correction_max, || stats.correction_max as f64);
+    }
+    *stats = PredictionStats::default();
+}
+
+/// Registers the snapshot buffer diagnostics of `C`.
+pub(crate) fn register_snapshot_diagnostics<C>(app: &mut App)
+where
+    C: Component + Interpolate + Clone,
+{
+    if app.world().contains_resource::<SnapDiagnostics<C>>() {
+        return;
+    }
+    let paths = SnapDiagnostics::<C>::new();
+    for path in [
+        &paths.buffer_depth,
+        &paths.snapshot_age,
+        &paths.interpolation_frozen,
+        &paths.extrapolation_time,
+        &paths.mispredictions,
+        &paths.correction_avg,
+        &paths.correction_max,
+    ] {
+        app.register_diagnostic(Diagnostic::new(path.clone()));
+    }
+    app.insert_resource(paths).add_systems(
+        PostUpdate,
+        snapshot_diagnostics_system::<C>.run_if(client_connected),
+    );
+}
+
+/// Registers the prediction diagnostics of `C`.
+pub(crate) fn register_prediction_diagnostics<C>(app: &mut App)
+where
+    C: Component + Interpolate + Clone,
+{
+    if app.world().contains_resource::<PredictionStats<C>>() {
+        return;
+    }
+    register_snapshot_diagnostics::<C>(app);
+    app.init_resource::<PredictionStats<C>>().add_systems(
+        PostUpdate,
+        // Corrections are only measured with a `PredictionTolerance`
+        prediction_diagnostics_system::<C>
+            .run_if(client_connected.and(resource_exists::<PredictionTolerance<C>>)),
+    );
+}
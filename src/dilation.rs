@@ -0,0 +1,190 @@
+use bevy::prelude::*;
+use bevy_replicon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::interpolation::SnapshotInterpolationConfig;
+
+/// Keeps the inputs of each client arriving a little ahead of the server.
+///
+/// Inputs sent with `PredictedInputWriter` are stamped with the tick they were predicted at.
+/// The server measures how far ahead of its own tick they arrive and reports it back, and
+/// the client speeds up or slows down `Time<Predicted>`, which stamps and steps its inputs,
+/// until the lead matches `target_lead`.
+/// Must be added on both the server and the clients.
+pub struct TimeDilationPlugin {
+    /// Server ticks the inputs of a client should arrive ahead of the server.
+    pub target_lead: f32,
+}
+
+impl Default for TimeDilationPlugin {
+    fn default() -> Self {
+        Self { target_lead: 2.0 }
+    }
+}
+
+/// Sent by the server to a client with how many ticks its inputs arrive ahead of the server.
+#[derive(Event, Deserialize, Serialize, Clone, Copy, Debug)]
+pub struct InputLeadReport {
+    /// Tick of the latest input received from the client.
+    pub tick: u32,
+    /// Negative values mean the inputs arrive late.
+    pub lead: f32,
+}
+
+/// Smoothed input lead of a client, stored on the client entity on the server.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct InputLead {
+    pub tick: u32,
+    pub lead: f32,
+}
+
+/// Lead changes in ticks that are not smoothed, because the client jumped its timeline.
+const LEAD_RESET: f32 = 5.0;
+
+impl InputLead {
+    /// Lead of an input stamped with `tick` that arrived at `server_tick`.
+    ///
+    /// Jitter is smoothed out, unless the lead is new or the client jumped.
+    pub fn measure(previous: Option<&InputLead>, tick: u32, server_tick: u32) -> Self {
+        let lead = tick as f32 - server_tick as f32;
+        let lead = match previous {
+            Some(previous) if (lead - previous.lead).abs() < LEAD_RESET => {
+                previous.lead + (lead - previous.lead) * 0.1
+            }
+            _ => lead,
+        };
+        Self { tick, lead }
+    }
+}
+
+/// Speed factor of the predicted timeline on a client.
+#[derive(Resource, Debug)]
+pub struct TimeDilation {
+    pub target_lead: f32,
+    /// Largest speed change used to reach the target, `0.05` is ±5%.
+    pub max_adjustment: f32,
+    /// Ticks the lead may be off before `Time<Predicted>` jumps instead.
+    pub jump_threshold: f32,
+    /// Latest lead reported by the server.
+    pub lead: f32,
+    /// Current speed of the predicted timeline relative to real time.
+    pub factor: f32,
+    /// Ticks `Time<Predicted>` has to jump on its next update.
+    pub pending_jump: f32,
+    /// Latest tick inputs were stamped with before the latest jump.
+    ///
+    /// Reports up to this tick were measured on the old timeline and are ignored. After a
+    /// backward jump this also skips fresh inputs until the timeline has passed it again.
+    pub jumped_from: u32,
+}
+
+impl Plugin for TimeDilationPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(TimeDilation {
+            target_lead: self.target_lead,
+            max_adjustment: 0.05,
+            jump_threshold: 10.0,
+            lead: self.target_lead,
+            factor: 1.0,
+            pending_jump: 0.0,
+            jumped_from: 0,
+        })
+            .add_server_trigger::<InputLeadReport>(Channel::Unreliable)
+            .add_observer(input_lead_receive_system)
+            .add_systems(
+                PostUpdate,
+                input_lead_report_system
+                    .before(ServerSet::Send)
+                    .run_if(server_running),
+            );
+    }
+}
+
+/// Sends every client its input lead with each update.
+pub fn input_lead_report_system(
+    q_clients: Query<(Entity, &InputLead), (With<ConnectedClient>, Changed<InputLead>)>,
+    mut commands: Commands,
+) {
+    for (client_entity, lead) in q_clients.iter() {
+        commands.server_trigger(ToClients {
+            mode: SendMode::Direct(client_entity),
+            event: InputLeadReport {
+                tick: lead.tick,
+                lead: lead.lead,
+            },
+        });
+    }
+}
+
+/// Adjusts the speed of the predicted timeline to the reported lead.
+pub fn input_lead_receive_system(
+    trigger: Trigger<InputLeadReport>,
+    mut dilation: ResMut<TimeDilation>,
+    config: Res<SnapshotInterpolationConfig>,
+) {
+    let report = trigger.event();
+    if report.tick <= dilation.jumped_from || dilation.pending_jump != 0.0 {
+        return;
+    }
+    let lead = report.lead;
+    let error = dilation.target_lead - lead;
+    dilation.lead = lead;
+    if error.abs() > dilation.jump_threshold {
+        dilation.pending_jump = error;
+        dilation.factor = 1.0;
+        return;
+    }
+    let max_adjustment = dilation.max_adjustment;
+    let error_secs = error / config.max_tick_rate as f32;
+    dilation.factor = 1.0 + error_secs.clamp(-max_adjustment, max_adjustment);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{prediction::Predicted, test_utils::connected_apps};
+
+    #[test]
+    fn stale_reports_are_ignored_after_backward_jump() {
+        let (_server_app, mut client_app, _) = connected_apps(|app| {
+            app.add_plugins(TimeDilationPlugin::default());
+        });
+        let tick_rate = client_app
+            .world()
+            .resource::<SnapshotInterpolationConfig>()
+            .max_tick_rate as f64;
+        client_app
+            .world_mut()
+            .resource_mut::<Time<Predicted>>()
+            .advance_to(Duration::from_secs_f64(100.0 / tick_rate));
+
+        // Inputs arrive far too early, the predicted timeline jumps back
+        client_app.world_mut().trigger(InputLeadReport {
+            tick: 100,
+            lead: 20.0,
+        });
+        client_app.update();
+        let predicted_tick =
+            client_app.world().resource::<Time<Predicted>>().elapsed_secs_f64() * tick_rate;
+        assert!((predicted_tick - 82.0).abs() < 0.01);
+
+        // Inputs stamped before the jump are still on their way
+        client_app.world_mut().trigger(InputLeadReport {
+            tick: 99,
+            lead: 19.0,
+        });
+        let dilation = client_app.world().resource::<TimeDilation>();
+        assert_eq!(dilation.pending_jump, 0.0);
+        assert_eq!(dilation.lead, 20.0);
+
+        client_app.world_mut().trigger(InputLeadReport {
+            tick: 101,
+            lead: 2.5,
+        });
+        let dilation = client_app.world().resource::<TimeDilation>();
+        assert_eq!(dilation.pending_jump, 0.0);
+        assert_eq!(dilation.lead, 2.5);
+    }
+}
@@ -0,0 +1,274 @@
+use std::{collections::VecDeque, marker::PhantomData};
+
+use bevy::{ecs::component::Mutable, prelude::*};
+use bevy_replicon::{
+    prelude::*,
+    shared::replication::{
+        deferred_entity::DeferredEntity,
+        replication_registry::{
+            ctx::{RemoveCtx, WriteCtx},
+            rule_fns::RuleFns,
+        },
+    },
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    interpolation::LastConfirmedTick,
+    prediction::{EventSnapshot, Predict, Predicted, PredictedInput, PredictedSince},
+    InterpolationSet,
+};
+
+/// Latest server value of a component predicted with `predict_discrete_event_for_component`.
+#[derive(Component, Clone, Debug)]
+pub struct Confirmed<C> {
+    pub tick: u32,
+    pub value: C,
+}
+
+/// Local events applied to the discretely predicted component `C` of an entity since the
+/// latest server value.
+#[derive(Component)]
+pub struct DiscreteEventHistory<E: Event, C> {
+    pub events: VecDeque<EventSnapshot<E>>,
+    marker: PhantomData<C>,
+}
+
+impl<E: Event, C> Default for DiscreteEventHistory<E, C> {
+    fn default() -> Self {
+        Self {
+            events: VecDeque::new(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<E: Event, C> DiscreteEventHistory<E, C> {
+    /// Drops events the server has already applied to `tick`.
+    pub fn remove_stale(&mut self, tick: u32) {
+        self.events.retain(|event| event.tick >= tick);
+    }
+}
+
+/// Stores the received value of a predicted entity as `Confirmed` instead of overwriting
+/// the prediction.
+pub fn write_confirmed_component<C: Clone + Component>(
+    ctx: &mut WriteCtx,
+    rule_fns: &RuleFns<C>,
+    entity: &mut DeferredEntity,
+    cursor: &mut bevy_replicon::bytes::Bytes,
+) -> Result<()> {
+    let value: C = rule_fns.deserialize(ctx, cursor)?;
+    let tick = ctx.message_tick.get();
+    if !entity.contains::<C>() {
+        entity.insert(value.clone());
+    }
+    if let Some(mut confirmed) = entity.get_mut::<Confirmed<C>>() {
+        *confirmed = Confirmed { tick, value };
+    } else {
+        entity.insert(Confirmed { tick, value });
+    }
+
+    Ok(())
+}
+
+fn remove_confirmed_component<C: Component>(ctx: &mut RemoveCtx, entity: &mut DeferredEntity) {
+    ctx.commands
+        .entity(entity.id())
+        .remove::<Confirmed<C>>()
+        .remove::<C>();
+}
+
+/// Applies a local event to discretely predicted components and remembers it for replays.
+pub fn discrete_predicted_update_system<
+    E: Event + Clone,
+    T: Component,
+    C: Component<Mutability=Mutable> + Predict<E, T>,
+>(
+    input: Trigger<PredictedInput<E>>,
+    mut q_predicted: Query<
+        (Entity, &mut C, &T, Option<&mut DiscreteEventHistory<E, C>>),
+        With<Predicted>,
+    >,
+    mut commands: Commands,
+) {
+    let input = input.event();
+    for (e, mut component, context, history) in q_predicted.iter_mut() {
+        component.apply_event(&input.event, input.delta_time, context);
+        let event = EventSnapshot {
+            value: input.event.clone(),
+            tick: input.tick,
+            delta_time: input.delta_time,
+        };
+        match history {
+            Some(mut history) => history.events.push_back(event),
+            None => {
+                let mut history = DiscreteEventHistory::<E, C>::default();
+                history.events.push_back(event);
+                commands.entity(e).insert(history);
+            }
+        }
+    }
+}
+
+/// Replays pending local events on top of the server value at every confirmed tick.
+///
+/// The server only sends `C` when it changes, so an event it rejected is only rolled back by
+/// the next confirmed tick, at which `Confirmed<C>` is still the server value.
+pub fn discrete_correction_system<
+    E: Event,
+    T: Component,
+    C: Component<Mutability=Mutable> + Predict<E, T> + Clone,
+>(
+    mut q_predicted: Query<
+        (
+            &mut C,
+            Ref<Confirmed<C>>,
+            &T,
+            Option<&mut DiscreteEventHistory<E, C>>,
+            Option<&PredictedSince>,
+        ),
+        With<Predicted>,
+    >,
+    last_confirmed: Res<LastConfirmedTick>,
+) {
+    for (mut component, confirmed, context, history, since) in q_predicted.iter_mut() {
+        if !confirmed.is_changed() && !last_confirmed.is_changed() {
+            continue;
+        }
+        let mut corrected = confirmed.value.clone();
+        if let Some(mut history) = history {
+            // The server has applied everything before the confirmed tick
+            history.remove_stale(confirmed.tick.max(last_confirmed.tick));
+            let since = since.map_or(0, |s| s.0);
+            for event in history.events.iter().filter(|e| e.tick >= since) {
+                corrected.apply_event(&event.value, event.delta_time, context);
+            }
+        }
+        *component = corrected;
+    }
+}
+
+/// Registers `Predicted` as the marker that redirects received values into `Confirmed`.
+struct DiscretePredictionPlugin;
+
+impl Plugin for DiscretePredictionPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_marker::<Predicted>();
+    }
+}
+
+/// Registers `C` to be replicated, with received values of predicted entities stored
+/// as `Confirmed<C>`.
+pub(crate) fn register_discrete_prediction<C>(app: &mut App) -> &mut App
+where
+    C: Component<Mutability=Mutable> + Clone + Serialize + DeserializeOwned,
+{
+    if !app.is_plugin_added::<DiscretePredictionPlugin>() {
+        app.add_plugins(DiscretePredictionPlugin);
+    }
+    app.replicate::<C>().set_marker_fns::<Predicted, C>(
+        write_confirmed_component::<C>,
+        remove_confirmed_component::<C>,
+    )
+}
+
+/// Registers the prediction systems of an event and a discretely predicted component.
+pub(crate) fn register_discrete_event<E, T, C>(app: &mut App) -> &mut App
+where
+    E: Event + Clone,
+    T: Component,
+    C: Component<Mutability=Mutable> + Predict<E, T> + Clone,
+{
+    app.add_observer(discrete_predicted_update_system::<E, T, C>)
+        .add_systems(
+            PreUpdate,
+            discrete_correction_system::<E, T, C>
+                .in_set(InterpolationSet::Interpolate)
+                .run_if(client_connected),
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Event, Clone, Debug)]
+    struct Fire;
+
+    #[derive(Component)]
+    struct Weapon;
+
+    #[derive(Component, Clone, Debug, PartialEq)]
+    struct Ammo(u8);
+
+    impl Predict<Fire, Weapon> for Ammo {
+        fn apply_event(&mut self, _event: &Fire, _delta_time: f32, _context: &Weapon) {
+            self.0 = self.0.saturating_sub(1);
+        }
+    }
+
+    fn predicted_ammo(app: &mut App, value: u8) -> Entity {
+        app.world_mut()
+            .spawn((
+                Predicted,
+                Weapon,
+                Ammo(value),
+                Confirmed {
+                    tick: 10,
+                    value: Ammo(value),
+                },
+            ))
+            .id()
+    }
+
+    fn fire(app: &mut App, tick: u32) {
+        app.world_mut().trigger(PredictedInput {
+            tick,
+            delta_time: 0.0,
+            event: Fire,
+        });
+    }
+
+    fn confirm(app: &mut App, tick: u32) {
+        *app.world_mut().resource_mut::<LastConfirmedTick>() = LastConfirmedTick { tick, gap: 1 };
+        app.update();
+    }
+
+    #[test]
+    fn rejected_event_rolls_back_at_confirmed_tick() {
+        let mut app = App::new();
+        app.init_resource::<LastConfirmedTick>()
+            .add_observer(discrete_predicted_update_system::<Fire, Weapon, Ammo>)
+            .add_systems(Update, discrete_correction_system::<Fire, Weapon, Ammo>);
+        let entity = predicted_ammo(&mut app, 5);
+        app.update();
+
+        fire(&mut app, 12);
+        assert_eq!(app.world().get::<Ammo>(entity), Some(&Ammo(4)));
+
+        // The server has not reached the event yet
+        confirm(&mut app, 11);
+        assert_eq!(app.world().get::<Ammo>(entity), Some(&Ammo(4)));
+
+        // The server passed the event without changing the ammo
+        confirm(&mut app, 13);
+        assert_eq!(app.world().get::<Ammo>(entity), Some(&Ammo(5)));
+    }
+
+    #[test]
+    fn events_are_replayed_per_entity() {
+        let mut app = App::new();
+        app.init_resource::<LastConfirmedTick>()
+            .add_observer(discrete_predicted_update_system::<Fire, Weapon, Ammo>)
+            .add_systems(Update, discrete_correction_system::<Fire, Weapon, Ammo>);
+        let first = predicted_ammo(&mut app, 5);
+        app.update();
+        fire(&mut app, 12);
+
+        let second = predicted_ammo(&mut app, 5);
+        confirm(&mut app, 11);
+        assert_eq!(app.world().get::<Ammo>(first), Some(&Ammo(4)));
+        assert_eq!(app.world().get::<Ammo>(second), Some(&Ammo(5)));
+    }
+}
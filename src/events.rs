@@ -0,0 +1,87 @@
+use std::marker::PhantomData;
+
+use bevy::prelude::*;
+
+/// Triggered on an entity when a new snapshot of `C` arrived from the server.
+#[derive(Event)]
+pub struct SnapshotReceived<C> {
+    pub entity: Entity,
+    /// Server tick of the snapshot.
+    pub tick: u32,
+    /// Value of the snapshot.
+    pub value: C,
+}
+
+impl<C> SnapshotReceived<C> {
+    pub fn new(entity: Entity, tick: u32, value: C) -> Self {
+        Self {
+            entity,
+            tick,
+            value,
+        }
+    }
+}
+
+/// Triggered on a predicted entity when the server corrected its value of `C`.
+///
+/// Requires a `PredictionTolerance<C>` to measure the correction.
+#[derive(Event)]
+pub struct Mispredicted<C> {
+    pub entity: Entity,
+    /// Value that was predicted before the correction.
+    pub predicted: C,
+    /// Value after replaying pending inputs on top of the latest server snapshot.
+    pub corrected: C,
+    /// Number of inputs that were replayed.
+    pub replayed_inputs: usize,
+}
+
+/// Triggered on an interpolated entity when interpolation of `C` reached the latest snapshot
+/// and has to wait for the next one.
+#[derive(Event)]
+pub struct InterpolationStarved<C> {
+    pub entity: Entity,
+    marker: PhantomData<C>,
+}
+
+impl<C> InterpolationStarved<C> {
+    pub fn new(entity: Entity) -> Self {
+        Self {
+            entity,
+            marker: PhantomData,
+        }
+    }
+}
+
+/// Triggered on a predicted entity when its predicted value of `C` does not match the
+/// server's checksum for the same tick.
+#[derive(Event)]
+pub struct Desync<C> {
+    pub entity: Entity,
+    /// Server tick the checksum was taken at.
+    pub tick: u32,
+    /// Value the client predicted for the tick.
+    pub predicted: C,
+    /// Latest buffered server value at or before the tick.
+    pub server: Option<C>,
+}
+
+/// Triggered on an entity that changed after a gap in the confirmed ticks when the snapshots
+/// of `C` from before the gap were dropped.
+#[derive(Event)]
+pub struct SnapshotBufferReset<C> {
+    pub entity: Entity,
+    /// Server ticks between the previous and the new confirmed tick.
+    pub gap_ticks: u32,
+    marker: PhantomData<C>,
+}
+
+impl<C> SnapshotBufferReset<C> {
+    pub fn new(entity: Entity, gap_ticks: u32) -> Self {
+        Self {
+            entity,
+            gap_ticks,
+            marker: PhantomData,
+        }
+    }
+}
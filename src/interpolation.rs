@@ -1,13 +1,15 @@
-use std::collections::VecDeque;
+use std::{collections::VecDeque, marker::PhantomData};
 
 use bevy::{ecs::component::Mutable, prelude::*};
 use bevy_replicon::{
+    client::{confirm_history::ConfirmHistory, server_mutate_ticks::ServerMutateTicks},
     prelude::*,
     shared::replication::{
         command_markers::MarkerConfig,
         deferred_entity::DeferredEntity,
         replication_registry::{
-            ctx::{RemoveCtx, WriteCtx},
+            command_fns::WriteFn,
+            ctx::{DespawnCtx, RemoveCtx, WriteCtx},
             rule_fns::RuleFns,
         },
     },
@@ -15,7 +17,21 @@ use bevy_replicon::{
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
 use crate::{
-    prediction::{owner_prediction_init_system, predicted_snapshot_system, Predicted},
+    prediction::{
+        predicted_snapshot_system, prediction_handoff_init_system, prediction_handoff_system,
+        prediction_mode_init_system, Predicted,
+    },
+    delta::{register_delta_compression, Diff},
+    diagnostics::register_snapshot_diagnostics,
+    events::{InterpolationStarved, SnapshotBufferReset, SnapshotReceived},
+    profile::{
+        InterpolationCurve, InterpolationProfile, InterpolationProfileName, InterpolationProfiles,
+    },
+    quantize::{quantized_rule_fns, Quantize},
+    spectator::{
+        spectator_buffer_init_system, spectator_buffer_prune_system, spectator_clock_system,
+        spectator_interpolation_into_system, spectator_interpolation_system, SpectatorClock,
+    },
     InterpolationSet,
 };
 
@@ -23,7 +39,48 @@ pub trait Interpolate {
     fn interpolate(&self, other: Self, t: f32) -> Self;
 }
 
-#[derive(Component, Deserialize, Serialize, Reflect)]
+/// Distance between two values of an interpolated field, used by `#[interpolate(distance)]`.
+pub trait FieldDistance {
+    fn field_distance(&self, other: &Self) -> f32;
+}
+
+impl FieldDistance for f32 {
+    fn field_distance(&self, other: &Self) -> f32 {
+        (self - other).abs()
+    }
+}
+
+impl FieldDistance for Vec2 {
+    fn field_distance(&self, other: &Self) -> f32 {
+        self.distance(*other)
+    }
+}
+
+impl FieldDistance for Vec3 {
+    fn field_distance(&self, other: &Self) -> f32 {
+        self.distance(*other)
+    }
+}
+
+impl FieldDistance for Vec3A {
+    fn field_distance(&self, other: &Self) -> f32 {
+        self.distance(*other)
+    }
+}
+
+impl FieldDistance for Vec4 {
+    fn field_distance(&self, other: &Self) -> f32 {
+        self.distance(*other)
+    }
+}
+
+impl FieldDistance for Quat {
+    fn field_distance(&self, other: &Self) -> f32 {
+        self.angle_between(*other)
+    }
+}
+
+#[derive(Component, Deserialize, Serialize, Reflect, Default)]
 pub struct Interpolated;
 
 #[derive(Deserialize, Serialize, Reflect)]
@@ -35,33 +92,138 @@ pub struct Snapshot<T: Component + Interpolate + Clone> {
 #[derive(Component, Deserialize, Serialize, Reflect)]
 pub struct SnapshotBuffer<T: Component + Interpolate + Clone> {
     pub buffer: VecDeque<T>,
+    /// Server tick of each snapshot in `buffer`.
+    pub ticks: VecDeque<u32>,
+    /// Maximum number of buffered snapshots.
+    pub capacity: usize,
     pub time_since_last_snapshot: f32,
     pub latest_snapshot_tick: u32,
+    /// Interpolation reached the latest snapshot and waits for a new one.
+    pub starved: bool,
 }
 
 #[derive(Resource, Serialize, Deserialize, Debug)]
 pub struct SnapshotInterpolationConfig {
+    /// Server tick rate, kept in sync with the server through `ServerTickRate`.
     pub max_tick_rate: u16,
 }
 
+/// Sent by the server to tell clients its tick rate.
+///
+/// Sent to every client once it is authorized and to all clients whenever the server's
+/// `SnapshotInterpolationConfig` changes.
+#[derive(Event, Deserialize, Serialize, Clone, Copy, Debug)]
+pub struct ServerTickRate(pub u16);
+
 #[derive(Component)]
 pub struct RecordSnapshotsMarker;
 
+/// How gaps in the updates received from the server are handled, e.g. after a connection stall.
+#[derive(Resource, Serialize, Deserialize, Debug)]
+pub struct SnapshotGapConfig {
+    /// Seconds of missed updates after which buffers are reset instead of interpolated.
+    pub max_gap: f32,
+    /// Seconds to fade `InterpolationFade` in after an entity appeared or its buffer was reset,
+    /// and out before it is despawned. Fading is disabled with `None`.
+    pub fade_duration: Option<f32>,
+}
+
+impl SnapshotGapConfig {
+    pub fn max_gap_ticks(&self, tick_rate: u16) -> u32 {
+        (self.max_gap * tick_rate as f32) as u32
+    }
+}
+
+/// Latest server tick whose mutations have all been received by the client and the ticks since
+/// the one confirmed before it.
+///
+/// Components that were not mutated at a confirmed tick still hold their value from it, so
+/// idle entities are confirmed along with everything else. The gap is only large when the
+/// client missed updates, or when nothing changed on the server for that long.
+#[derive(Resource, Default, Debug)]
+pub struct LastConfirmedTick {
+    pub tick: u32,
+    pub gap: u32,
+}
+
+impl Default for SnapshotGapConfig {
+    fn default() -> Self {
+        Self {
+            max_gap: 1.0,
+            fade_duration: None,
+        }
+    }
+}
+
+/// Opacity of an interpolated entity, maintained while `SnapshotGapConfig::fade_duration` is set.
+///
+/// Apply it to the entity's material to fade entities in and out.
+#[derive(Component, Debug)]
+pub struct InterpolationFade {
+    pub alpha: f32,
+}
+
+/// Interpolated entity whose first snapshot has not been reached by the displayed timeline.
+///
+/// With the `pending_visibility` feature, entities with `Visibility` are hidden until then.
+#[derive(Component, Default)]
+pub struct InterpolationPending {
+    /// Visibility to restore once the entity is shown.
+    #[cfg(feature = "pending_visibility")]
+    pub visibility: Option<Visibility>,
+}
+
+/// Interpolated entity that was despawned on the server at `tick` and is despawned locally
+/// once the displayed timeline reaches it.
+#[derive(Component)]
+pub struct PendingDespawn {
+    pub tick: u32,
+}
+
+/// Removal of `C` from an interpolated entity that waits for the displayed timeline.
+#[derive(Component)]
+pub struct PendingRemoval<C> {
+    /// Server tick the component was removed at.
+    pub tick: u32,
+    marker: PhantomData<C>,
+}
+
+impl<C> PendingRemoval<C> {
+    pub fn new(tick: u32) -> Self {
+        Self {
+            tick,
+            marker: PhantomData,
+        }
+    }
+}
+
+/// Mapping used by `replicate_interpolated_into` to write an interpolated `Src` into `Dst`.
+#[derive(Resource)]
+pub struct InterpolationTarget<Src, Dst> {
+    pub map: fn(&Src, &mut Dst),
+}
+
 impl<T: Component + Interpolate + Clone> SnapshotBuffer<T> {
     pub fn new() -> Self {
         Self {
             buffer: VecDeque::new(),
+            ticks: VecDeque::new(),
+            capacity: 10,
             time_since_last_snapshot: 0.0,
             latest_snapshot_tick: 0,
+            starved: false,
         }
     }
     pub fn insert(&mut self, element: T, tick: u32) {
-        if self.buffer.len() > 9 {
+        while self.buffer.len() >= self.capacity.max(1) {
             self.buffer.pop_front();
+            self.ticks.pop_front();
         }
         self.buffer.push_back(element);
+        self.ticks.push_back(tick);
         self.time_since_last_snapshot = 0.0;
         self.latest_snapshot_tick = tick;
+        self.starved = false;
     }
 
     pub fn latest_snapshot(&self) -> T {
@@ -75,108 +237,882 @@ impl<T: Component + Interpolate + Clone> SnapshotBuffer<T> {
     pub fn age(&self) -> f32 {
         self.time_since_last_snapshot
     }
+
+    /// Server ticks between the two latest snapshots.
+    ///
+    /// Only changed components are replicated, so this is larger than one for entities
+    /// that update irregularly.
+    pub fn latest_interval_ticks(&self) -> u32 {
+        match self.ticks.len() {
+            0 | 1 => 1,
+            len => self.ticks[len - 1].saturating_sub(self.ticks[len - 2]).max(1),
+        }
+    }
+
+    /// Fractional server tick that is displayed with `profile`, one interval and the profile delay
+    /// behind the latest snapshot.
+    pub fn render_tick(&self, profile: &InterpolationProfile, tick_duration: f32) -> f64 {
+        let lag_ticks = self.latest_interval_ticks() as f32 + profile.delay
+            - self.time_since_last_snapshot / tick_duration;
+        self.latest_snapshot_tick as f64 - lag_ticks as f64
+    }
+
+    /// Value at a fractional server tick, interpolated between the two snapshots around it.
+    /// Ticks outside of the buffered window return the oldest or latest snapshot.
+    pub fn sample(&self, tick: f64) -> Option<T> {
+        self.sample_curve(tick, InterpolationCurve::Linear)
+    }
+
+    /// Like `sample`, but the progress between the two snapshots follows `curve`.
+    pub fn sample_curve(&self, tick: f64, curve: InterpolationCurve) -> Option<T> {
+        let next = self.ticks.iter().position(|&t| t as f64 > tick);
+        match next {
+            None => self.buffer.back().cloned(),
+            Some(0) => self.buffer.front().cloned(),
+            Some(next) => {
+                let (from_tick, to_tick) = (self.ticks[next - 1], self.ticks[next]);
+                let t = (tick - from_tick as f64) / (to_tick - from_tick) as f64;
+                let t = curve.apply(t as f32);
+                Some(self.buffer[next - 1].interpolate(self.buffer[next].clone(), t))
+            }
+        }
+    }
 }
 
 /// Interpolate between snapshots.
 pub fn snapshot_interpolation_system<T: Component<Mutability=Mutable> + Interpolate + Clone>(
-    mut q: Query<(&mut T, &mut SnapshotBuffer<T>), (With<Interpolated>, Without<Predicted>)>,
+    mut q: Query<
+        (
+            Entity,
+            &mut T,
+            &mut SnapshotBuffer<T>,
+            Option<&InterpolationProfileName>,
+        ),
+        (With<Interpolated>, Without<Predicted>),
+    >,
+    profiles: Res<InterpolationProfiles>,
     time: Res<Time>,
     config: Res<SnapshotInterpolationConfig>,
+    mut commands: Commands,
 ) {
-    for (mut component, mut snapshot_buffer) in q.iter_mut() {
-        let buffer = &snapshot_buffer.buffer;
-        let elapsed = snapshot_buffer.time_since_last_snapshot;
-        if buffer.len() < 2 {
+    let tick_duration = 1.0 / (config.max_tick_rate as f32);
+    for (e, mut component, mut snapshot_buffer, profile) in q.iter_mut() {
+        let value = advance_interpolation(
+            &mut commands,
+            e,
+            &mut snapshot_buffer,
+            profiles.get(profile),
+            time.delta_secs(),
+            tick_duration,
+        );
+        if let Some(value) = value {
+            *component = value;
+        }
+    }
+}
+
+/// Interpolate between snapshots of `Src` and write the result into `Dst`.
+pub fn snapshot_interpolation_into_system<
+    Src: Component + Interpolate + Clone,
+    Dst: Component<Mutability=Mutable> + Default,
+>(
+    mut q: Query<
+        (
+            Entity,
+            &mut SnapshotBuffer<Src>,
+            Option<&mut Dst>,
+            Option<&InterpolationProfileName>,
+        ),
+        (With<Interpolated>, Without<Predicted>),
+    >,
+    target: Res<InterpolationTarget<Src, Dst>>,
+    profiles: Res<InterpolationProfiles>,
+    time: Res<Time>,
+    config: Res<SnapshotInterpolationConfig>,
+    mut commands: Commands,
+) {
+    let tick_duration = 1.0 / (config.max_tick_rate as f32);
+    for (e, mut snapshot_buffer, visual, profile) in q.iter_mut() {
+        let Some(value) = advance_interpolation(
+            &mut commands,
+            e,
+            &mut snapshot_buffer,
+            profiles.get(profile),
+            time.delta_secs(),
+            tick_duration,
+        ) else {
             continue;
+        };
+        match visual {
+            Some(mut visual) => (target.map)(&value, &mut visual),
+            None => {
+                let mut visual = Dst::default();
+                (target.map)(&value, &mut visual);
+                commands.entity(e).insert(visual);
+            }
+        }
+    }
+}
+
+/// Copy `Src` into `Dst` for entities that are not interpolated.
+pub fn sync_into_system<
+    Src: Component + Interpolate + Clone,
+    Dst: Component<Mutability=Mutable> + Default,
+>(
+    mut q: Query<(Entity, &Src, Option<&mut Dst>), (Changed<Src>, Without<Interpolated>)>,
+    target: Res<InterpolationTarget<Src, Dst>>,
+    mut commands: Commands,
+) {
+    for (e, value, visual) in q.iter_mut() {
+        match visual {
+            Some(mut visual) => (target.map)(value, &mut visual),
+            None => {
+                let mut visual = Dst::default();
+                (target.map)(value, &mut visual);
+                commands.entity(e).insert(visual);
+            }
         }
+    }
+}
 
-        let tick_duration = 1.0 / (config.max_tick_rate as f32);
+/// Returns the value between the two latest snapshots and advances the buffer time.
+///
+/// The interval between them is derived from their server ticks, so entities that are not
+/// updated every tick still move at the right speed.
+/// The profile can delay the displayed value further into the buffer, extrapolate past the
+/// latest snapshot and change the curve between snapshots.
+/// Triggers `InterpolationStarved` when the buffer runs out of snapshots.
+fn advance_interpolation<T: Component + Interpolate + Clone>(
+    commands: &mut Commands,
+    entity: Entity,
+    snapshot_buffer: &mut SnapshotBuffer<T>,
+    profile: &InterpolationProfile,
+    delta_secs: f32,
+    tick_duration: f32,
+) -> Option<T> {
+    let len = snapshot_buffer.buffer.len();
+    if len < 2 {
+        return None;
+    }
+    if profile.curve == InterpolationCurve::Step {
+        snapshot_buffer.time_since_last_snapshot += delta_secs;
+        return Some(snapshot_buffer.latest_snapshot());
+    }
 
-        if elapsed > tick_duration + time.delta_secs() {
-            continue;
+    let interval_ticks = snapshot_buffer.latest_interval_ticks() as f32;
+    let latest_tick = snapshot_buffer.latest_snapshot_tick as f64;
+    let render_tick = snapshot_buffer.render_tick(profile, tick_duration);
+    let overshoot = (render_tick - latest_tick) as f32 * tick_duration;
+    if overshoot > profile.max_extrapolation + delta_secs {
+        if !snapshot_buffer.starved {
+            snapshot_buffer.starved = true;
+            commands.trigger_targets(InterpolationStarved::<T>::new(entity), entity);
         }
+        return None;
+    }
 
-        let t = (elapsed / tick_duration).clamp(0., 1.);
-        *component = buffer[0].interpolate(buffer[1].clone(), t);
-        snapshot_buffer.time_since_last_snapshot += time.delta_secs();
+    let value = if overshoot > 0.0 && profile.max_extrapolation > 0.0 {
+        // Continue along the latest interval
+        let buffer = &snapshot_buffer.buffer;
+        let t = 1.0 + overshoot / (interval_ticks * tick_duration);
+        buffer[len - 2].interpolate(buffer[len - 1].clone(), t)
+    } else {
+        snapshot_buffer.sample_curve(render_tick, profile.curve)?
+    };
+    snapshot_buffer.time_since_last_snapshot += delta_secs;
+    Some(value)
+}
+
+/// Sends the tick rate to a newly authorized client.
+pub fn server_tick_rate_connect_system(
+    trigger: Trigger<OnAdd, AuthorizedClient>,
+    config: Res<SnapshotInterpolationConfig>,
+    mut commands: Commands,
+) {
+    commands.server_trigger(ToClients {
+        mode: SendMode::Direct(trigger.target()),
+        event: ServerTickRate(config.max_tick_rate),
+    });
+}
+
+/// Seconds over which the server tick rate is measured.
+const TICK_RATE_WINDOW: f32 = 1.0;
+
+/// Server tick and time at the start of the current tick rate measurement.
+#[derive(Resource, Default, Debug)]
+pub struct TickRateMeasurement {
+    pub start: Option<(u32, f32)>,
+}
+
+/// Measures how fast `ServerTick` advances, so clients follow the actual tick policy.
+///
+/// Updates `SnapshotInterpolationConfig` when the measured rate differs by more than 5%.
+pub fn server_tick_rate_measure_system(
+    mut measurement: ResMut<TickRateMeasurement>,
+    mut config: ResMut<SnapshotInterpolationConfig>,
+    server_tick: Res<ServerTick>,
+    time: Res<Time>,
+) {
+    let tick = server_tick.get();
+    let now = time.elapsed_secs();
+    let Some((start_tick, start)) = measurement.start else {
+        measurement.start = Some((tick, now));
+        return;
+    };
+    let elapsed = now - start;
+    if elapsed < TICK_RATE_WINDOW {
+        return;
+    }
+    measurement.start = Some((tick, now));
+
+    let ticks = tick.wrapping_sub(start_tick);
+    if ticks == 0 {
+        return;
+    }
+    let measured = (ticks as f32 / elapsed).round().clamp(1.0, u16::MAX as f32);
+    let current = config.max_tick_rate as f32;
+    if (measured - current).abs() > current * 0.05 {
+        debug!("measured server tick rate of {measured}");
+        config.max_tick_rate = measured as u16;
+    }
+}
+
+/// Sends the tick rate to all clients after it changed on the server.
+pub fn server_tick_rate_changed_system(
+    config: Res<SnapshotInterpolationConfig>,
+    mut commands: Commands,
+) {
+    commands.server_trigger(ToClients {
+        mode: SendMode::Broadcast,
+        event: ServerTickRate(config.max_tick_rate),
+    });
+}
+
+/// Applies the tick rate sent by the server.
+pub fn tick_rate_receive_system(
+    trigger: Trigger<ServerTickRate>,
+    mut config: ResMut<SnapshotInterpolationConfig>,
+) {
+    let tick_rate = trigger.event().0;
+    if tick_rate != 0 && config.max_tick_rate != tick_rate {
+        debug!("server tick rate changed to {tick_rate}");
+        config.max_tick_rate = tick_rate;
     }
 }
 
 /// Add a marker to all components requiring a snapshot buffer
+///
+/// The value that arrived with the spawn becomes the first snapshot, and interpolated
+/// entities wait for the displayed timeline to reach it.
 pub fn snapshot_buffer_init_system<T: Component + Interpolate + Clone>(
-    q_new: Query<(Entity, &T), Or<(Added<Predicted>, Added<Interpolated>)>>,
+    q_new: Query<
+        (
+            Entity,
+            &T,
+            Has<Interpolated>,
+            Option<&SnapshotBuffer<T>>,
+            Option<&ConfirmHistory>,
+        ),
+        Or<(Added<Predicted>, Added<Interpolated>)>,
+    >,
+    mut commands: Commands,
+) {
+    for (e, value, interpolated, snapshot_buffer, confirmed) in q_new.iter() {
+        let mut entity = commands.entity(e);
+        entity.insert(RecordSnapshotsMarker);
+        let Some(confirmed) = confirmed else {
+            continue;
+        };
+        if snapshot_buffer.is_none() {
+            let mut snapshot_buffer = SnapshotBuffer::new();
+            snapshot_buffer.insert(value.clone(), confirmed.last_tick().get());
+            entity.insert(snapshot_buffer);
+            if interpolated {
+                entity.insert(InterpolationPending::default());
+            }
+        }
+    }
+}
+
+/// Advances `LastConfirmedTick` once all mutations of a newer tick have been received, and
+/// starts fading interpolated entities that changed after a gap back in.
+pub fn confirm_gap_system(
+    q: Query<(Entity, &ConfirmHistory), With<Interpolated>>,
+    mutate_ticks: Res<ServerMutateTicks>,
+    mut last_confirmed: ResMut<LastConfirmedTick>,
+    gap_config: Res<SnapshotGapConfig>,
+    config: Res<SnapshotInterpolationConfig>,
+    mut commands: Commands,
+) {
+    let tick = mutate_ticks.last_tick();
+    if !mutate_ticks.contains(tick) || tick.get() <= last_confirmed.tick {
+        return;
+    }
+    let previous = last_confirmed.tick;
+    // Nothing was missed before the first confirmation
+    let gap = if previous == 0 { 0 } else { tick.get() - previous };
+    *last_confirmed = LastConfirmedTick {
+        tick: tick.get(),
+        gap,
+    };
+
+    let max_gap_ticks = gap_config.max_gap_ticks(config.max_tick_rate);
+    if gap <= max_gap_ticks || gap_config.fade_duration.is_none() {
+        return;
+    }
+    for (e, confirm_history) in q.iter() {
+        // Entities that didn't change since before the gap still show the right value
+        if confirm_history.last_tick().get() > previous {
+            commands.entity(e).insert(InterpolationFade { alpha: 0.0 });
+        }
+    }
+}
+
+/// Forgets the confirmed tick after disconnecting.
+pub fn confirm_gap_reset_system(mut last_confirmed: ResMut<LastConfirmedTick>) {
+    *last_confirmed = LastConfirmedTick::default();
+}
+
+/// Sizes snapshot buffers by the profile of their entity when the buffer is added, the entity
+/// changes its profile or the profiles change.
+pub fn snapshot_buffer_profile_system<T: Component + Interpolate + Clone>(
+    mut q: Query<(&mut SnapshotBuffer<T>, Option<Ref<InterpolationProfileName>>)>,
+    profiles: Res<InterpolationProfiles>,
+) {
+    for (mut snapshot_buffer, profile) in q.iter_mut() {
+        let changed = profiles.is_changed()
+            || snapshot_buffer.is_added()
+            || profile.as_ref().is_some_and(|profile| profile.is_changed());
+        if !changed {
+            continue;
+        }
+        let capacity = profiles.get(profile.as_deref()).buffer_len;
+        if snapshot_buffer.capacity != capacity {
+            snapshot_buffer.capacity = capacity;
+        }
+    }
+}
+
+/// Resets buffers of entities that changed after a gap in the confirmed ticks, so the entity
+/// jumps to the first snapshot after it instead of moving across everything it missed.
+pub fn snapshot_gap_system<T: Component<Mutability=Mutable> + Interpolate + Clone>(
+    mut q: Query<(Entity, &mut T, &mut SnapshotBuffer<T>), With<Interpolated>>,
+    last_confirmed: Res<LastConfirmedTick>,
+    gap_config: Res<SnapshotGapConfig>,
+    config: Res<SnapshotInterpolationConfig>,
+    mut commands: Commands,
+) {
+    let max_gap_ticks = gap_config.max_gap_ticks(config.max_tick_rate);
+    if !last_confirmed.is_changed() || last_confirmed.gap <= max_gap_ticks {
+        return;
+    }
+    let previous = last_confirmed.tick - last_confirmed.gap;
+    for (e, mut component, mut snapshot_buffer) in q.iter_mut() {
+        // Idle entities keep interpolating towards their latest snapshot
+        if snapshot_buffer.latest_snapshot_tick() <= previous {
+            continue;
+        }
+
+        // Snapshots from before the gap
+        let len = snapshot_buffer.ticks.len();
+        let stale = snapshot_buffer
+            .ticks
+            .iter()
+            .take_while(|tick| **tick <= previous)
+            .count()
+            .min(len.saturating_sub(1));
+        snapshot_buffer.buffer.drain(..stale);
+        snapshot_buffer.ticks.drain(..stale);
+        snapshot_buffer.starved = false;
+        if let Some(first) = snapshot_buffer.buffer.front() {
+            *component = first.clone();
+        }
+        commands.trigger_targets(SnapshotBufferReset::<T>::new(e, last_confirmed.gap), e);
+    }
+}
+
+/// Fades interpolated entities in after they appeared or their buffer was reset, and out
+/// while their despawn is pending.
+pub fn interpolation_fade_system(
+    mut q: Query<
+        (Entity, Has<PendingDespawn>, Option<&mut InterpolationFade>),
+        (With<Interpolated>, Without<InterpolationPending>),
+    >,
+    gap_config: Res<SnapshotGapConfig>,
+    time: Res<Time>,
+    mut commands: Commands,
+) {
+    let Some(fade_duration) = gap_config.fade_duration else {
+        return;
+    };
+    let step = time.delta_secs() / fade_duration.max(f32::EPSILON);
+    for (e, despawning, fade) in q.iter_mut() {
+        let target = if despawning { 0.0 } else { 1.0 };
+        match fade {
+            Some(mut fade) => {
+                let alpha = fade.alpha + (target - fade.alpha).clamp(-step, step);
+                if alpha != fade.alpha {
+                    fade.alpha = alpha;
+                }
+            }
+            None => {
+                commands.entity(e).insert(InterpolationFade { alpha: 0.0 });
+            }
+        }
+    }
+}
+
+/// Shows interpolated entities once the displayed timeline, delayed by their profile,
+/// reached their first snapshot.
+pub fn interpolation_pending_system<T: Component + Interpolate + Clone>(
+    q: Query<
+        (Entity, &SnapshotBuffer<T>, Option<&InterpolationProfileName>),
+        With<InterpolationPending>,
+    >,
+    interpolated_time: Res<Time<Interpolated>>,
+    config: Res<SnapshotInterpolationConfig>,
+    profiles: Res<InterpolationProfiles>,
+    mut commands: Commands,
+) {
+    let displayed_tick = interpolated_time.elapsed_secs_f64() * config.max_tick_rate as f64;
+    for (e, snapshot_buffer, profile) in q.iter() {
+        let Some(&first_tick) = snapshot_buffer.ticks.front() else {
+            continue;
+        };
+        let delay = profiles.get(profile).delay as f64;
+        if displayed_tick - delay >= first_tick as f64 {
+            commands.entity(e).remove::<InterpolationPending>();
+        }
+    }
+}
+
+/// Hides an entity while it waits for the displayed timeline.
+#[cfg(feature = "pending_visibility")]
+pub fn hide_pending_system(
+    trigger: Trigger<OnAdd, InterpolationPending>,
+    mut q: Query<(&mut InterpolationPending, &mut Visibility)>,
+) {
+    if let Ok((mut pending, mut visibility)) = q.get_mut(trigger.target()) {
+        pending.visibility = Some(*visibility);
+        *visibility = Visibility::Hidden;
+    }
+}
+
+/// Restores the visibility an entity had before it was hidden.
+#[cfg(feature = "pending_visibility")]
+pub fn show_pending_system(
+    trigger: Trigger<OnRemove, InterpolationPending>,
+    mut q: Query<(&InterpolationPending, &mut Visibility)>,
+) {
+    if let Ok((pending, mut visibility)) = q.get_mut(trigger.target()) {
+        if let Some(previous) = pending.visibility {
+            *visibility = previous;
+        }
+    }
+}
+
+/// Despawns entities right away, except interpolated ones which are despawned once the
+/// displayed timeline reaches the despawn tick.
+pub fn despawn_interpolated(ctx: &DespawnCtx, mut entity: EntityWorldMut) {
+    if entity.contains::<Interpolated>() && !entity.contains::<Predicted>() {
+        entity.insert(PendingDespawn {
+            tick: ctx.message_tick.get(),
+        });
+    } else {
+        entity.despawn();
+    }
+}
+
+/// Despawns entities whose despawn tick has been reached by `Time<Interpolated>`, delayed by
+/// their profile.
+pub fn pending_despawn_system(
+    q: Query<(
+        Entity,
+        &PendingDespawn,
+        Option<&InterpolationFade>,
+        Option<&InterpolationProfileName>,
+    )>,
+    interpolated_time: Res<Time<Interpolated>>,
+    config: Res<SnapshotInterpolationConfig>,
+    profiles: Res<InterpolationProfiles>,
     mut commands: Commands,
 ) {
-    for (e, _v) in q_new.iter() {
-        commands.entity(e).insert(RecordSnapshotsMarker);
+    let displayed_tick = interpolated_time.elapsed_secs_f64() * config.max_tick_rate as f64;
+    for (e, pending, fade, profile) in q.iter() {
+        // Faded entities are kept until they are fully transparent
+        let faded_out = fade.is_none_or(|fade| fade.alpha <= 0.0);
+        let delay = profiles.get(profile).delay as f64;
+        if displayed_tick - delay >= pending.tick as f64 && faded_out {
+            commands.entity(e).despawn();
+        }
     }
 }
 
-pub fn write_snap_component<C: Clone + Interpolate + Component + DeserializeOwned>(
+pub fn write_snap_component<C: Clone + Interpolate + Component>(
+    ctx: &mut WriteCtx,
+    rule_fns: &RuleFns<C>,
+    entity: &mut DeferredEntity,
+    cursor: &mut bevy_replicon::bytes::Bytes,
+) -> Result<()> {
+    let component: C = rule_fns.deserialize(ctx, cursor)?;
+    insert_snapshot(ctx, entity, component);
+
+    Ok(())
+}
+
+/// Like `write_snap_component`, but also writes the received value into the component itself,
+/// so it always holds the latest authoritative value.
+///
+/// Predicted entities are left to prediction, which replays pending inputs on top of the snapshot.
+pub fn write_authoritative_snap_component<
+    C: Clone + Interpolate + Component<Mutability=Mutable>,
+>(
     ctx: &mut WriteCtx,
     rule_fns: &RuleFns<C>,
     entity: &mut DeferredEntity,
     cursor: &mut bevy_replicon::bytes::Bytes,
 ) -> Result<()> {
     let component: C = rule_fns.deserialize(ctx, cursor)?;
+    insert_snapshot(ctx, entity, component.clone());
+    let predicted = entity.contains::<Predicted>();
+    if let Some(mut current) = entity.get_mut::<C>() {
+        if !predicted {
+            *current = component;
+        }
+    } else {
+        entity.insert(component);
+    }
+
+    Ok(())
+}
+
+pub(crate) fn insert_snapshot<C: Clone + Interpolate + Component>(
+    ctx: &mut WriteCtx,
+    entity: &mut DeferredEntity,
+    component: C,
+) {
     if let Some(mut buffer) = entity.get_mut::<SnapshotBuffer<C>>() {
-        buffer.insert(component, ctx.message_tick.get());
+        buffer.insert(component.clone(), ctx.message_tick.get());
     } else {
         let mut buffer = SnapshotBuffer::new();
-        buffer.insert(component, ctx.message_tick.get());
+        buffer.insert(component.clone(), ctx.message_tick.get());
         ctx.commands.entity(entity.id()).insert(buffer);
     }
-
-    Ok(())
+    if entity.contains::<PendingRemoval<C>>() {
+        // Inserted again before the removal was displayed
+        ctx.commands.entity(entity.id()).remove::<PendingRemoval<C>>();
+    }
+    ctx.commands.trigger_targets(
+        SnapshotReceived::new(entity.id(), ctx.message_tick.get(), component),
+        entity.id(),
+    );
 }
 
-fn remove_snap_component<C: Clone + Interpolate + Component + DeserializeOwned>(
+/// Removes the component, or defers the removal of interpolated entities until the
+/// displayed timeline reaches the server tick of the removal.
+fn remove_snap_component<C: Clone + Interpolate + Component>(
     ctx: &mut RemoveCtx,
     entity: &mut DeferredEntity,
 ) {
+    if entity.contains::<Interpolated>() && !entity.contains::<Predicted>() {
+        ctx.commands
+            .entity(entity.id())
+            .insert(PendingRemoval::<C>::new(ctx.message_tick.get()));
+        return;
+    }
     ctx.commands
         .entity(entity.id())
         .remove::<SnapshotBuffer<C>>()
         .remove::<C>();
 }
 
+/// Applies deferred removals of `C` once `Time<Interpolated>`, delayed by the profile of the
+/// entity, reaches their tick.
+pub fn pending_removal_system<C: Component>(
+    q: Query<(Entity, &PendingRemoval<C>, Option<&InterpolationProfileName>)>,
+    interpolated_time: Res<Time<Interpolated>>,
+    config: Res<SnapshotInterpolationConfig>,
+    profiles: Res<InterpolationProfiles>,
+    mut commands: Commands,
+) {
+    let displayed_tick = interpolated_time.elapsed_secs_f64() * config.max_tick_rate as f64;
+    for (e, removal, profile) in q.iter() {
+        let delay = profiles.get(profile).delay as f64;
+        if displayed_tick - delay >= removal.tick as f64 {
+            commands
+                .entity(e)
+                .remove::<(PendingRemoval<C>, SnapshotBuffer<C>, C)>();
+        }
+    }
+}
+
 pub trait AppInterpolationExt {
     /// Register a component to be replicated and interpolated between server updates
     /// Requires the component to implement the Interpolate trait
     fn replicate_interpolated<C>(&mut self) -> &mut Self
     where
         C: Component<Mutability=Mutable> + Interpolate + Clone + Serialize + DeserializeOwned;
+
+    /// Register a component to be replicated and interpolated into a separate visual component.
+    /// `Src` keeps the latest authoritative value and `map` writes the interpolated value
+    /// into `Dst`, e.g. to drive a `Transform` from a replicated position.
+    fn replicate_interpolated_into<Src, Dst>(&mut self, map: fn(&Src, &mut Dst)) -> &mut Self
+    where
+        Src: Component<Mutability=Mutable> + Interpolate + Clone + Serialize + DeserializeOwned,
+        Dst: Component<Mutability=Mutable> + Default;
+
+    /// Like `replicate_interpolated`, but with custom serialization functions.
+    fn replicate_interpolated_with<C>(&mut self, rule_fns: RuleFns<C>) -> &mut Self
+    where
+        C: Component<Mutability=Mutable> + Interpolate + Clone;
+
+    /// Like `replicate_interpolated`, but sends the component with its `Quantize` encoding.
+    fn replicate_interpolated_quantized<C>(&mut self) -> &mut Self
+    where
+        C: Component<Mutability=Mutable> + Interpolate + Quantize + Clone;
+
+    /// Like `replicate_interpolated`, but sends the component encoded relative to the latest
+    /// value acknowledged by clients, using its `Diff` implementation.
+    /// Falls back to sending the full value when no baseline is available.
+    fn replicate_interpolated_delta<C>(&mut self) -> &mut Self
+    where
+        C: Component<Mutability=Mutable>
+            + Interpolate
+            + Diff
+            + Clone
+            + Serialize
+            + DeserializeOwned;
+
+    /// Add a named interpolation profile, selected per entity with `InterpolationProfileName`.
+    fn add_interpolation_profile(
+        &mut self,
+        name: impl Into<String>,
+        profile: InterpolationProfile,
+    ) -> &mut Self;
 }
 
 impl AppInterpolationExt for App {
+    fn add_interpolation_profile(
+        &mut self,
+        name: impl Into<String>,
+        profile: InterpolationProfile,
+    ) -> &mut Self {
+        self.world_mut()
+            .resource_mut::<InterpolationProfiles>()
+            .insert(name, profile);
+        self
+    }
+
     fn replicate_interpolated<T>(&mut self) -> &mut Self
     where
         T: Component<Mutability=Mutable> + Interpolate + Clone + Serialize + DeserializeOwned,
     {
-        self.add_systems(
-            PreUpdate,
-            (snapshot_buffer_init_system::<T>.after(owner_prediction_init_system))
-                .in_set(InterpolationSet::Init)
-                .run_if(client_connected),
-        );
-        self.add_systems(
-            PreUpdate,
+        self.replicate_interpolated_with(RuleFns::<T>::default())
+    }
+
+    fn replicate_interpolated_with<T>(&mut self, rule_fns: RuleFns<T>) -> &mut Self
+    where
+        T: Component<Mutability=Mutable> + Interpolate + Clone,
+    {
+        add_interpolation_systems::<T>(self);
+        add_snapshot_buffer_systems::<T>(self);
+        register_snapshot_buffer(self, rule_fns, write_snap_component::<T>)
+    }
+
+    fn replicate_interpolated_delta<T>(&mut self) -> &mut Self
+    where
+        T: Component<Mutability=Mutable>
+            + Interpolate
+            + Diff
+            + Clone
+            + Serialize
+            + DeserializeOwned,
+    {
+        add_interpolation_systems::<T>(self);
+        add_snapshot_buffer_systems::<T>(self);
+        register_delta_compression::<T>(self)
+    }
+
+    fn replicate_interpolated_quantized<T>(&mut self) -> &mut Self
+    where
+        T: Component<Mutability=Mutable> + Interpolate + Quantize + Clone,
+    {
+        self.replicate_interpolated_with(quantized_rule_fns::<T>())
+    }
+
+    fn replicate_interpolated_into<Src, Dst>(&mut self, map: fn(&Src, &mut Dst)) -> &mut Self
+    where
+        Src: Component<Mutability=Mutable> + Interpolate + Clone + Serialize + DeserializeOwned,
+        Dst: Component<Mutability=Mutable> + Default,
+    {
+        self.insert_resource(InterpolationTarget { map })
+            .add_systems(
+                PreUpdate,
+                (
+                    (
+                        snapshot_interpolation_into_system::<Src, Dst>,
+                        predicted_snapshot_system::<Src>,
+                        prediction_handoff_system::<Src>,
+                    )
+                        .chain()
+                        .run_if(not(resource_exists::<SpectatorClock>)),
+                    spectator_interpolation_into_system::<Src, Dst>
+                        .after(spectator_clock_system)
+                        .run_if(resource_exists::<SpectatorClock>),
+                    sync_into_system::<Src, Dst>,
+                )
+                    .chain()
+                    .in_set(InterpolationSet::Interpolate)
+                    .run_if(client_connected),
+            );
+        add_snapshot_buffer_systems::<Src>(self);
+        register_snapshot_diagnostics::<Src>(self);
+        register_snapshot_buffer(
+            self,
+            RuleFns::<Src>::default(),
+            write_authoritative_snap_component::<Src>,
+        )
+    }
+}
+
+fn add_interpolation_systems<T>(app: &mut App)
+where
+    T: Component<Mutability=Mutable> + Interpolate + Clone,
+{
+    register_snapshot_diagnostics::<T>(app);
+    app.add_systems(
+        PreUpdate,
+        (
             (
                 snapshot_interpolation_system::<T>,
                 predicted_snapshot_system::<T>,
+                prediction_handoff_system::<T>,
             )
                 .chain()
+                .run_if(not(resource_exists::<SpectatorClock>)),
+            spectator_interpolation_system::<T>
+                .after(spectator_clock_system)
+                .run_if(resource_exists::<SpectatorClock>),
+        )
+            .in_set(InterpolationSet::Interpolate)
+            .run_if(client_connected),
+    );
+}
+
+fn add_snapshot_buffer_systems<T>(app: &mut App)
+where
+    T: Component<Mutability=Mutable> + Interpolate + Clone,
+{
+    app.add_systems(
+        PreUpdate,
+        (
+            snapshot_buffer_init_system::<T>.after(prediction_mode_init_system),
+            snapshot_buffer_profile_system::<T>
+                .after(snapshot_buffer_init_system::<T>)
+                .run_if(not(resource_exists::<SpectatorClock>)),
+            (
+                spectator_buffer_init_system::<T>,
+                spectator_buffer_prune_system::<T>,
+            )
+                .chain()
+                .run_if(resource_exists::<SpectatorClock>),
+        )
+            .in_set(InterpolationSet::Init)
+            .run_if(client_connected),
+    )
+        .add_systems(
+            PreUpdate,
+            (
+                pending_removal_system::<T>,
+                interpolation_pending_system::<T>,
+            )
                 .in_set(InterpolationSet::Interpolate)
                 .run_if(client_connected),
         )
-            .replicate::<T>()
-            .register_marker_with::<RecordSnapshotsMarker>(MarkerConfig {
-                need_history: true,
-                ..default()
+        .add_systems(
+            PreUpdate,
+            snapshot_gap_system::<T>
+                .after(confirm_gap_system)
+                .in_set(InterpolationSet::Init)
+                .run_if(client_connected),
+        )
+        .add_observer(prediction_handoff_init_system::<T>);
+}
+
+fn register_snapshot_buffer<T>(app: &mut App, rule_fns: RuleFns<T>, write: WriteFn<T>) -> &mut App
+where
+    T: Component<Mutability=Mutable> + Interpolate + Clone,
+{
+    app.replicate_with(rule_fns)
+        .register_marker_with::<RecordSnapshotsMarker>(MarkerConfig {
+            need_history: true,
+            ..default()
+        })
+        .set_marker_fns::<RecordSnapshotsMarker, T>(write, remove_snap_component::<T>)
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_replicon::test_app::ServerTestAppExt;
+
+    use super::*;
+    use crate::test_utils::connected_apps;
+
+    #[derive(Component, Deserialize, Serialize, Clone, Debug)]
+    struct Position(f32);
+
+    impl Interpolate for Position {
+        fn interpolate(&self, other: Self, t: f32) -> Self {
+            Self(self.0 + (other.0 - self.0) * t)
+        }
+    }
+
+    #[derive(Resource, Default)]
+    struct Resets(u32);
+
+    #[test]
+    fn idle_entity_moves_without_reset() {
+        let (mut server_app, mut client_app, _) = connected_apps(|app| {
+            app.replicate_interpolated::<Position>();
+        });
+        client_app
+            .insert_resource(SnapshotGapConfig {
+                max_gap: 0.05,
+                fade_duration: Some(0.25),
             })
-            .set_marker_fns::<RecordSnapshotsMarker, T>(
-                write_snap_component::<T>,
-                remove_snap_component::<T>,
-            )
+            .init_resource::<Resets>()
+            .add_observer(
+                |_trigger: Trigger<SnapshotBufferReset<Position>>, mut resets: ResMut<Resets>| {
+                    resets.0 += 1;
+                },
+            );
+        let idle = server_app
+            .world_mut()
+            .spawn((Replicated, Interpolated, Position(0.0)))
+            .id();
+        let moving = server_app
+            .world_mut()
+            .spawn((Replicated, Interpolated, Position(0.0)))
+            .id();
+
+        let max_gap_ticks = client_app
+            .world()
+            .resource::<SnapshotGapConfig>()
+            .max_gap_ticks(60);
+        for _ in 0..max_gap_ticks * 3 {
+            server_app.world_mut().get_mut::<Position>(moving).unwrap().0 += 1.0;
+            server_app.update();
+            server_app.exchange_with_client(&mut client_app);
+            client_app.update();
+        }
+
+        server_app.world_mut().get_mut::<Position>(idle).unwrap().0 = 5.0;
+        server_app.update();
+        server_app.exchange_with_client(&mut client_app);
+        client_app.update();
+
+        let last_confirmed = client_app.world().resource::<LastConfirmedTick>();
+        assert!(last_confirmed.gap <= max_gap_ticks);
+        assert_eq!(client_app.world().resource::<Resets>().0, 0);
     }
 }
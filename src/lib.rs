@@ -1,22 +1,60 @@
 use std::fmt::Debug;
 
 use bevy::prelude::*;
-use bevy_replicon::prelude::*;
+use bevy_replicon::{prelude::*, shared::replication::replication_registry::ReplicationRegistry};
 use bevy_replicon_renet::renet::ClientId;
 use serde::{Deserialize, Serialize};
 
 pub use bevy_replicon_snap_macros;
 
 use crate::{
-    interpolation::{Interpolated, SnapshotInterpolationConfig},
-    prediction::{owner_prediction_init_system, OwnerPredicted, Predicted},
+    clock::{network_clock_reset_system, network_clock_system, NetworkClocks},
+    interpolation::{
+        confirm_gap_reset_system, confirm_gap_system, despawn_interpolated,
+        interpolation_fade_system, pending_despawn_system, server_tick_rate_changed_system,
+        server_tick_rate_connect_system, server_tick_rate_measure_system, tick_rate_receive_system,
+        Interpolated, LastConfirmedTick, ServerTickRate, SnapshotGapConfig,
+        SnapshotInterpolationConfig, TickRateMeasurement,
+    },
+    predicted_spawn::{
+        provisional_confirm_system, provisional_reject_system, provisional_timeout_system,
+        server_abandoned_spawn_system, server_predicted_spawn_system, PredictedSpawnAbandoned,
+        PredictedSpawnConfig, PredictedSpawnRejected, PredictionKey, Provisional,
+    },
+    prediction::{
+        local_client_cleanup_system, local_client_init_system, prediction_mode_init_system,
+        server_local_client_system, OwnershipHandoffConfig, OwnerPredicted, Predicted,
+        PredictedSince, PredictionMode,
+    },
+    profile::{InterpolationProfileName, InterpolationProfiles},
 };
 
+pub mod clock;
+#[cfg(feature = "debug_gizmos")]
+pub mod debug;
+pub mod delta;
+pub mod desync;
+pub mod diagnostics;
+pub mod dilation;
+pub mod discrete;
+pub mod events;
 pub mod interpolation;
+pub mod predicted_resource;
+pub mod predicted_spawn;
 pub mod prediction;
+pub mod profile;
+pub mod quantize;
+pub mod record;
+pub mod smoothing;
+pub mod spectator;
+#[cfg(test)]
+mod test_utils;
 
 pub struct SnapshotInterpolationPlugin {
-    /// Should reflect the server max tick rate
+    /// Should reflect the server max tick rate.
+    ///
+    /// The server replaces it with the measured tick rate and clients only use it until the
+    /// server has sent its own.
     pub max_tick_rate: u16,
 }
 
@@ -26,6 +64,16 @@ pub struct NetworkOwner(pub Entity);
 #[derive(Component, Deserialize, Serialize, Reflect, Deref)]
 pub struct ClientNetId(pub ClientId);
 
+/// The server-side entity of this client, as used in `NetworkOwner`.
+///
+/// Inserted on clients once the server has sent the identity.
+#[derive(Resource, Reflect, Deref, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LocalClient(pub Entity);
+
+/// Sent by the server to each client to tell it its own server-side entity.
+#[derive(Event, Deserialize, Serialize, Clone, Copy, Debug)]
+pub struct LocalClientIdentity(pub Entity);
+
 /// Sets for interpolation systems.
 #[derive(SystemSet, Debug, Hash, PartialEq, Eq, Clone, Copy)]
 pub enum InterpolationSet {
@@ -43,21 +91,101 @@ impl Plugin for SnapshotInterpolationPlugin {
     fn build(&self, app: &mut App) {
         app.register_type::<Interpolated>()
             .register_type::<OwnerPredicted>()
+            .register_type::<PredictionMode>()
             .register_type::<NetworkOwner>()
+            .register_type::<LocalClient>()
             .register_type::<Predicted>()
+            .register_type::<PredictedSince>()
+            .register_type::<PredictionKey>()
+            .register_type::<Provisional>()
+            .register_type::<InterpolationProfileName>()
             .replicate::<Interpolated>()
             .replicate::<NetworkOwner>()
             .replicate::<OwnerPredicted>()
+            .replicate::<PredictionMode>()
+            .replicate::<InterpolationProfileName>()
+            .add_server_trigger::<PredictedSpawnRejected>(Channel::Ordered)
+            .add_client_trigger::<PredictedSpawnAbandoned>(Channel::Ordered)
+            .add_server_trigger::<LocalClientIdentity>(Channel::Ordered)
+            .add_server_trigger::<ServerTickRate>(Channel::Ordered)
             .configure_sets(PreUpdate, InterpolationSet::Init.after(ClientSet::Receive))
             .configure_sets(
                 PreUpdate,
                 InterpolationSet::Interpolate.after(InterpolationSet::Init),
             )
+            .add_systems(
+                PreUpdate,
+                provisional_timeout_system
+                    .in_set(InterpolationSet::Init)
+                    .run_if(client_connected),
+            )
+            .add_systems(
+                PreUpdate,
+                network_clock_system
+                    .in_set(InterpolationSet::Init)
+                    .run_if(client_connected),
+            )
+            .add_systems(
+                PreUpdate,
+                confirm_gap_system
+                    .in_set(InterpolationSet::Init)
+                    .run_if(client_connected),
+            )
+            .add_systems(
+                PreUpdate,
+                (pending_despawn_system, interpolation_fade_system)
+                    .in_set(InterpolationSet::Interpolate)
+                    .run_if(client_connected),
+            )
+            .add_systems(
+                PreUpdate,
+                (
+                    local_client_cleanup_system,
+                    network_clock_reset_system,
+                    confirm_gap_reset_system,
+                )
+                    .run_if(client_just_disconnected),
+            )
+            .add_systems(
+                PostUpdate,
+                (
+                    server_tick_rate_measure_system.run_if(server_running),
+                    server_tick_rate_changed_system.run_if(
+                        server_running.and(resource_changed::<SnapshotInterpolationConfig>),
+                    ),
+                )
+                    .chain()
+                    .before(ServerSet::Send),
+            )
             .add_observer(
-                owner_prediction_init_system
+                prediction_mode_init_system
             )
+            .add_observer(local_client_init_system)
+            .add_observer(server_local_client_system)
+            .add_observer(server_tick_rate_connect_system)
+            .add_observer(tick_rate_receive_system)
+            .add_observer(server_predicted_spawn_system)
+            .add_observer(server_abandoned_spawn_system)
+            .add_observer(provisional_confirm_system)
+            .add_observer(provisional_reject_system)
+            .init_resource::<PredictedSpawnConfig>()
+            .init_resource::<OwnershipHandoffConfig>()
+            .init_resource::<SnapshotGapConfig>()
+            .init_resource::<LastConfirmedTick>()
+            .init_resource::<InterpolationProfiles>()
+            .init_resource::<NetworkClocks>()
+            .init_resource::<TickRateMeasurement>()
+            .init_resource::<Time<Interpolated>>()
+            .init_resource::<Time<Predicted>>()
             .insert_resource(SnapshotInterpolationConfig {
                 max_tick_rate: self.max_tick_rate,
             });
+
+        #[cfg(feature = "pending_visibility")]
+        app.add_observer(interpolation::hide_pending_system)
+            .add_observer(interpolation::show_pending_system);
+
+        // Interpolated entities are despawned when the displayed timeline reaches the despawn
+        app.world_mut().resource_mut::<ReplicationRegistry>().despawn = despawn_interpolated;
     }
 }
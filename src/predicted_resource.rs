@@ -0,0 +1,227 @@
+use std::{collections::VecDeque, fmt::Debug};
+
+use bevy::prelude::*;
+use bevy_replicon::prelude::*;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::prediction::{EventSnapshot, PredictedInput, ServerInput};
+
+/// This trait defines how an event will mutate a given resource
+/// and is required for resource prediction.
+pub trait PredictResource<E: Event>
+where
+    Self: Resource,
+{
+    fn apply_event(&mut self, event: &E, delta_time: f32);
+}
+
+/// Server value of a predicted resource, sent to clients whenever it changes.
+#[derive(Event, Deserialize, Serialize, Clone, Debug)]
+pub struct ResourceSnapshot<R> {
+    pub tick: u32,
+    pub value: R,
+}
+
+/// Latest server value of a predicted resource and the local events applied since.
+///
+/// Only one event type is replayed per resource.
+#[derive(Resource)]
+pub struct PredictedResource<E: Event, R> {
+    pub confirmed: Option<ResourceSnapshot<R>>,
+    pub events: VecDeque<EventSnapshot<E>>,
+}
+
+impl<E: Event, R> Default for PredictedResource<E, R> {
+    fn default() -> Self {
+        Self {
+            confirmed: None,
+            events: VecDeque::new(),
+        }
+    }
+}
+
+impl<E: Event, R> PredictedResource<E, R> {
+    /// Drops events the server has already applied to `tick`.
+    pub fn remove_stale(&mut self, tick: u32) {
+        self.events.retain(|event| event.tick >= tick);
+    }
+}
+
+/// Applies client events to the authoritative resource.
+pub fn server_resource_update_system<E: Event, R: PredictResource<E>>(
+    trigger: Trigger<ServerInput<E>>,
+    resource: Option<ResMut<R>>,
+) {
+    if let Some(mut resource) = resource {
+        resource.apply_event(&trigger.input.event, trigger.input.delta_time);
+    }
+}
+
+/// Sends the resource to all clients after it changed on the server.
+///
+/// Like replication, it is only sent on frames that incremented the tick, after the increment.
+/// Inputs released on frames in between belong to the next tick, so the value contains exactly
+/// the inputs stamped before its tick.
+pub fn server_resource_send_system<R: Resource + Clone>(
+    resource: Res<R>,
+    server_tick: Res<ServerTick>,
+    mut commands: Commands,
+) {
+    commands.server_trigger(ToClients {
+        mode: SendMode::Broadcast,
+        event: ResourceSnapshot {
+            tick: server_tick.get(),
+            value: resource.clone(),
+        },
+    });
+}
+
+/// Sends the resource to a newly authorized client.
+pub fn server_resource_connect_system<R: Resource + Clone>(
+    trigger: Trigger<OnAdd, AuthorizedClient>,
+    resource: Option<Res<R>>,
+    server_tick: Res<ServerTick>,
+    mut commands: Commands,
+) {
+    let Some(resource) = resource else {
+        return;
+    };
+    commands.server_trigger(ToClients {
+        mode: SendMode::Direct(trigger.target()),
+        event: ResourceSnapshot {
+            tick: server_tick.get(),
+            value: resource.clone(),
+        },
+    });
+}
+
+/// Applies a local event to the predicted resource and remembers it for replays.
+pub fn predicted_resource_update_system<E: Event + Clone, R: PredictResource<E>>(
+    input: Trigger<PredictedInput<E>>,
+    resource: Option<ResMut<R>>,
+    mut history: ResMut<PredictedResource<E, R>>,
+) {
+    let Some(mut resource) = resource else {
+        return;
+    };
+    let input = input.event();
+    resource.apply_event(&input.event, input.delta_time);
+    history.events.push_back(EventSnapshot {
+        value: input.event.clone(),
+        tick: input.tick,
+        delta_time: input.delta_time,
+    });
+}
+
+/// Applies all pending local events to the new server value.
+pub fn predicted_resource_correction_system<E: Event, R: PredictResource<E> + Clone>(
+    trigger: Trigger<ResourceSnapshot<R>>,
+    mut history: ResMut<PredictedResource<E, R>>,
+    mut commands: Commands,
+) {
+    let snapshot = trigger.event().clone();
+    if history
+        .confirmed
+        .as_ref()
+        .is_some_and(|confirmed| confirmed.tick > snapshot.tick)
+    {
+        return;
+    }
+    history.remove_stale(snapshot.tick);
+
+    let mut corrected = snapshot.value.clone();
+    for event in history.events.iter() {
+        corrected.apply_event(&event.value, event.delta_time);
+    }
+    history.confirmed = Some(snapshot);
+    commands.insert_resource(corrected);
+}
+
+/// Registers `R` to be replicated by the server and predicted with `E` on clients.
+pub(crate) fn register_predicted_resource<E, R>(app: &mut App) -> &mut App
+where
+    E: Event + Serialize + DeserializeOwned + Debug + Clone,
+    R: PredictResource<E> + Clone + Serialize + DeserializeOwned,
+{
+    app.init_resource::<PredictedResource<E, R>>()
+        .add_server_trigger::<ResourceSnapshot<R>>(Channel::Ordered)
+        .add_observer(server_resource_update_system::<E, R>)
+        .add_observer(server_resource_connect_system::<R>)
+        .add_observer(predicted_resource_update_system::<E, R>)
+        .add_observer(predicted_resource_correction_system::<E, R>)
+        .add_systems(
+            PostUpdate,
+            server_resource_send_system::<R>
+                .after(ServerSet::Send)
+                .run_if(
+                    server_running
+                        .and(resource_changed::<ServerTick>)
+                        .and(resource_exists_and_changed::<R>),
+                ),
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_replicon::{server::TickPolicy, test_app::ServerTestAppExt};
+
+    use super::*;
+    use crate::{prediction::AppPredictionExt, test_utils::connected_apps_with};
+
+    #[derive(Event, Deserialize, Serialize, Clone, Debug)]
+    struct Add(u32);
+
+    #[derive(Resource, Deserialize, Serialize, Clone, Default, Debug)]
+    struct Counter(u32);
+
+    impl PredictResource<Add> for Counter {
+        fn apply_event(&mut self, event: &Add, _delta_time: f32) {
+            self.0 += event.0;
+        }
+    }
+
+    #[derive(Resource, Default)]
+    struct Received(Vec<(u32, u32)>);
+
+    #[test]
+    fn inputs_between_ticks_are_sent_with_next_tick() {
+        let (mut server_app, mut client_app, client_entity) =
+            connected_apps_with(TickPolicy::Manual, |app| {
+                app.add_client_predicted_event::<Add>(Channel::Ordered)
+                    .predict_event_for_resource::<Add, Counter>()
+                    .init_resource::<Counter>();
+            });
+        client_app.init_resource::<Received>().add_observer(
+            |trigger: Trigger<ResourceSnapshot<Counter>>, mut received: ResMut<Received>| {
+                received.0.push((trigger.tick, trigger.value.0));
+            },
+        );
+
+        server_app.world_mut().resource_mut::<ServerTick>().increment();
+        server_app.update();
+        let tick = server_app.world().resource::<ServerTick>().get();
+
+        // Several frames run per tick, the input of the current tick arrives on one of them
+        server_app.world_mut().trigger(FromClient {
+            client_entity,
+            event: PredictedInput {
+                tick,
+                delta_time: 0.0,
+                event: Add(1),
+            },
+        });
+        server_app.update();
+        server_app.update();
+        assert_eq!(server_app.world().resource::<Counter>().0, 1);
+
+        server_app.world_mut().resource_mut::<ServerTick>().increment();
+        server_app.update();
+        server_app.update();
+        server_app.exchange_with_client(&mut client_app);
+        client_app.update();
+
+        let received = &client_app.world().resource::<Received>().0;
+        assert!(!received.contains(&(tick, 1)));
+        assert!(received.contains(&(tick + 1, 1)));
+    }
+}
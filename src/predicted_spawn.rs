@@ -0,0 +1,267 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use bevy_replicon::client::confirm_history::ConfirmHistory;
+use bevy_replicon::prelude::*;
+use bevy_replicon::server::client_entity_map::ClientEntityMap;
+use serde::{Deserialize, Serialize};
+
+use crate::{prediction::Predicted, NetworkOwner};
+
+/// Identifies a client-side provisional entity.
+///
+/// The client sends this key along with the input that spawned the entity. The server attaches it
+/// to the entity it spawns in response, which maps the server entity onto the provisional one.
+#[derive(Component, Deserialize, Serialize, Reflect, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PredictionKey(pub u64);
+
+impl PredictionKey {
+    /// The provisional entity on the owning client.
+    pub fn entity(&self) -> Entity {
+        Entity::from_bits(self.0)
+    }
+}
+
+impl From<Entity> for PredictionKey {
+    fn from(entity: Entity) -> Self {
+        Self(entity.to_bits())
+    }
+}
+
+/// Marks an entity that was spawned by the client ahead of the server.
+///
+/// Removed once the server's replicated entity has been merged into it.
+#[derive(Component, Reflect, Default)]
+#[require(Predicted)]
+pub struct Provisional {
+    /// Seconds since the entity was spawned.
+    pub age: f32,
+}
+
+/// Sent by the server to the owning client when a predicted spawn will never be confirmed.
+///
+/// Never sent automatically: the server code that decides not to spawn the entity sends it to the
+/// owner with `SendMode::Direct`.
+#[derive(Event, Deserialize, Serialize, Clone, Copy, Debug)]
+pub struct PredictedSpawnRejected(pub PredictionKey);
+
+/// Sent by the owning client when it gave up waiting for a predicted spawn and despawned the
+/// provisional entity.
+#[derive(Event, Deserialize, Serialize, Clone, Copy, Debug)]
+pub struct PredictedSpawnAbandoned(pub PredictionKey);
+
+/// Keys of predicted spawns a client has abandoned, stored on the client entity on the server.
+///
+/// Server entities spawned with one of these keys are replicated as new entities instead of
+/// being mapped onto the despawned provisional entity.
+#[derive(Component, Default, Debug)]
+pub struct AbandonedSpawns {
+    pub keys: VecDeque<PredictionKey>,
+}
+
+/// Upper bound of abandoned keys remembered per client.
+const MAX_ABANDONED_SPAWNS: usize = 64;
+
+/// Triggered on the client when a provisional entity has been merged with its server entity.
+#[derive(Event, Clone, Copy, Debug)]
+pub struct PredictedSpawnConfirmed(pub Entity);
+
+#[derive(Resource, Serialize, Deserialize, Debug)]
+pub struct PredictedSpawnConfig {
+    /// Seconds to wait for the server entity before a provisional entity is despawned.
+    pub timeout: f32,
+}
+
+impl Default for PredictedSpawnConfig {
+    fn default() -> Self {
+        Self { timeout: 1.0 }
+    }
+}
+
+/// Maps a server entity spawned with a `PredictionKey` onto the owner's provisional entity,
+/// unless the owner has already abandoned it.
+pub fn server_predicted_spawn_system(
+    trigger: Trigger<OnAdd, PredictionKey>,
+    q_spawned: Query<(&PredictionKey, &NetworkOwner)>,
+    mut q_clients: Query<(&mut ClientEntityMap, Option<&mut AbandonedSpawns>)>,
+) {
+    let Ok((key, owner)) = q_spawned.get(trigger.target()) else {
+        return;
+    };
+    let Ok((mut entity_map, abandoned)) = q_clients.get_mut(owner.0) else {
+        return;
+    };
+    if let Some(mut abandoned) = abandoned {
+        if let Some(index) = abandoned.keys.iter().position(|abandoned| abandoned == key) {
+            abandoned.keys.remove(index);
+            return;
+        }
+    }
+    entity_map.insert(trigger.target(), key.entity());
+}
+
+/// Remembers a predicted spawn the client has abandoned.
+///
+/// If the server entity was already mapped onto the provisional one, the mapping has been sent
+/// and points at an entity the client no longer has, so the server entity is despawned instead.
+pub fn server_abandoned_spawn_system(
+    trigger: Trigger<FromClient<PredictedSpawnAbandoned>>,
+    q_spawned: Query<(Entity, &PredictionKey, &NetworkOwner)>,
+    mut q_clients: Query<&mut AbandonedSpawns>,
+    mut commands: Commands,
+) {
+    let key = trigger.event().0;
+    if let Some((spawned, ..)) = q_spawned
+        .iter()
+        .find(|(_, spawned_key, owner)| **spawned_key == key && owner.0 == trigger.client_entity)
+    {
+        commands.entity(spawned).despawn();
+        return;
+    }
+    match q_clients.get_mut(trigger.client_entity) {
+        Ok(mut abandoned) => {
+            if abandoned.keys.len() >= MAX_ABANDONED_SPAWNS {
+                abandoned.keys.pop_front();
+            }
+            abandoned.keys.push_back(key);
+        }
+        Err(_) => {
+            if let Ok(mut client) = commands.get_entity(trigger.client_entity) {
+                client.insert(AbandonedSpawns {
+                    keys: VecDeque::from([key]),
+                });
+            }
+        }
+    }
+}
+
+/// Confirms a provisional entity once replication for it has been received.
+pub fn provisional_confirm_system(
+    trigger: Trigger<OnAdd, ConfirmHistory>,
+    q_provisional: Query<(), With<Provisional>>,
+    mut commands: Commands,
+) {
+    if q_provisional.contains(trigger.target()) {
+        commands.entity(trigger.target()).remove::<Provisional>();
+        commands.trigger(PredictedSpawnConfirmed(trigger.target()));
+    }
+}
+
+/// Despawns a provisional entity the server has rejected.
+pub fn provisional_reject_system(
+    trigger: Trigger<PredictedSpawnRejected>,
+    q_provisional: Query<(), With<Provisional>>,
+    mut commands: Commands,
+) {
+    let entity = trigger.event().0.entity();
+    if q_provisional.contains(entity) {
+        commands.entity(entity).despawn();
+    }
+}
+
+/// Despawns provisional entities that were never confirmed by the server and tells the server,
+/// so a late server entity is not mapped onto the despawned one.
+pub fn provisional_timeout_system(
+    mut q_provisional: Query<(Entity, &mut Provisional)>,
+    time: Res<Time>,
+    config: Res<PredictedSpawnConfig>,
+    mut commands: Commands,
+) {
+    for (e, mut provisional) in q_provisional.iter_mut() {
+        provisional.age += time.delta_secs();
+        if provisional.age > config.timeout {
+            commands.entity(e).despawn();
+            commands.client_trigger(PredictedSpawnAbandoned(PredictionKey::from(e)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_replicon::test_app::ServerTestAppExt;
+
+    use super::*;
+    use crate::test_utils::connected_apps;
+
+    #[test]
+    fn server_entity_confirms_provisional() {
+        let (mut server_app, mut client_app, client_entity) = connected_apps(|_| {});
+        let provisional = client_app.world_mut().spawn(Provisional::default()).id();
+        server_app.world_mut().spawn((
+            Replicated,
+            NetworkOwner(client_entity),
+            PredictionKey::from(provisional),
+        ));
+
+        server_app.update();
+        server_app.exchange_with_client(&mut client_app);
+        client_app.update();
+
+        let provisional = client_app.world().entity(provisional);
+        assert!(!provisional.contains::<Provisional>());
+        assert!(provisional.contains::<ConfirmHistory>());
+    }
+
+    #[test]
+    fn abandoned_spawn_is_replicated_as_new_entity() {
+        let (mut server_app, mut client_app, client_entity) = connected_apps(|_| {});
+        client_app.insert_resource(PredictedSpawnConfig { timeout: 0.0 });
+        let provisional = client_app
+            .world_mut()
+            .spawn(Provisional { age: 1.0 })
+            .id();
+
+        client_app.update();
+        assert!(client_app.world().get_entity(provisional).is_err());
+        server_app.exchange_with_client(&mut client_app);
+        server_app.update();
+
+        let key = PredictionKey::from(provisional);
+        let abandoned = server_app.world().get::<AbandonedSpawns>(client_entity).unwrap();
+        assert_eq!(abandoned.keys, [key]);
+
+        server_app
+            .world_mut()
+            .spawn((Replicated, NetworkOwner(client_entity), key));
+        server_app.update();
+        let abandoned = server_app.world().get::<AbandonedSpawns>(client_entity).unwrap();
+        assert!(abandoned.keys.is_empty());
+
+        server_app.exchange_with_client(&mut client_app);
+        client_app.update();
+
+        let replicated = client_app
+            .world_mut()
+            .query_filtered::<Entity, With<ConfirmHistory>>()
+            .single(client_app.world())
+            .unwrap();
+        assert_ne!(replicated, provisional);
+    }
+
+    #[test]
+    fn late_abandon_despawns_mapped_entity() {
+        let (mut server_app, mut client_app, client_entity) = connected_apps(|_| {});
+        client_app.insert_resource(PredictedSpawnConfig { timeout: 0.0 });
+        let provisional = client_app
+            .world_mut()
+            .spawn(Provisional { age: 1.0 })
+            .id();
+        let spawned = server_app
+            .world_mut()
+            .spawn((
+                Replicated,
+                NetworkOwner(client_entity),
+                PredictionKey::from(provisional),
+            ))
+            .id();
+
+        // The mapping is sent before the client gives up, but arrives after it.
+        server_app.update();
+        client_app.update();
+        server_app.exchange_with_client(&mut client_app);
+        server_app.update();
+
+        assert!(server_app.world().get_entity(spawned).is_err());
+        assert!(server_app.world().get::<AbandonedSpawns>(client_entity).is_none());
+    }
+}
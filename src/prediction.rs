@@ -1,5 +1,13 @@
 use crate::{
-    interpolation::Interpolate, interpolation::SnapshotBuffer, Interpolated, NetworkOwner,
+    desync::DesyncDetection,
+    dilation::{InputLead, TimeDilation},
+    discrete::{register_discrete_event, register_discrete_prediction},
+    diagnostics::{register_prediction_diagnostics, PredictionStats},
+    events::Mispredicted,
+    interpolation::Interpolate, interpolation::RecordSnapshotsMarker,
+    interpolation::{SnapshotBuffer, SnapshotInterpolationConfig},
+    predicted_resource::{register_predicted_resource, PredictResource},
+    spectator::SpectatorClock, Interpolated, LocalClient, LocalClientIdentity, NetworkOwner,
 };
 use bevy::ecs::component::Mutable;
 use bevy::prelude::*;
@@ -9,16 +17,15 @@ use bevy::{
         component::Component,
         entity::Entity,
         event::{Event, EventReader},
-        query::{Added, With, Without},
+        query::{Added, QueryData, With, Without},
         resource::Resource,
-        system::{Commands, Query, Res, ResMut},
+        system::{Commands, Query, Res, ResMut, SystemParam},
     },
     reflect::Reflect,
     time::Time,
 };
 use bevy_replicon::client::confirm_history::ConfirmHistory;
 use bevy_replicon::prelude::*;
-use bevy_replicon::shared::backend::connected_client::NetworkId;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::collections::vec_deque::Iter;
 use std::collections::VecDeque;
@@ -28,7 +35,7 @@ use std::fmt::Debug;
 /// and is required for prediction.
 pub trait Predict<E: Event, T>
 where
-    Self: Component + Interpolate,
+    Self: Component,
 {
     fn apply_event(&mut self, event: &E, delta_time: f32, context: &T);
 }
@@ -42,12 +49,214 @@ pub struct EventSnapshot<T: Event> {
 #[derive(Resource)]
 pub struct PredictedEventHistory<T: Event>(pub VecDeque<EventSnapshot<T>>);
 
+/// Largest delta time in seconds the server applies for a single input.
+const MAX_INPUT_DELTA: f32 = 0.25;
+
+/// Ticks an input may be stamped ahead of the server before it is applied earlier.
+const MAX_INPUT_LEAD: u32 = 32;
+
+/// An input event stamped with the server tick it was predicted at and the predicted
+/// delta time it was applied with.
+///
+/// The server applies it once it reaches that tick, so a snapshot of tick `n` contains
+/// exactly the inputs stamped before `n`.
+#[derive(Event, Deserialize, Serialize, Clone, Debug)]
+pub struct PredictedInput<E> {
+    pub tick: u32,
+    pub delta_time: f32,
+    pub event: E,
+}
+
+/// Triggered on the server when it reaches the tick of a client's input.
+#[derive(Event, Clone, Debug)]
+pub struct ServerInput<E> {
+    pub client_entity: Entity,
+    pub input: PredictedInput<E>,
+}
+
+/// Inputs of a client that the server has not reached yet, stored on the client entity
+/// on the server.
+#[derive(Component)]
+pub struct InputBuffer<E> {
+    pub inputs: VecDeque<PredictedInput<E>>,
+}
+
+impl<E> Default for InputBuffer<E> {
+    fn default() -> Self {
+        Self {
+            inputs: VecDeque::new(),
+        }
+    }
+}
+
+impl<E> InputBuffer<E> {
+    /// Queues an input behind all inputs of the same or earlier ticks.
+    pub fn insert(&mut self, input: PredictedInput<E>) {
+        let index = self
+            .inputs
+            .partition_point(|queued| queued.tick <= input.tick);
+        self.inputs.insert(index, input);
+    }
+}
+
+/// Sends predicted inputs to the server.
+///
+/// On clients inputs are stamped from `Time<Predicted>` and predicted locally, on a
+/// listen server they are applied right away.
+#[derive(SystemParam)]
+pub struct PredictedInputWriter<'w, 's> {
+    commands: Commands<'w, 's>,
+    client: Res<'w, RepliconClient>,
+    predicted_time: Res<'w, Time<Predicted>>,
+    time: Res<'w, Time>,
+    config: Res<'w, SnapshotInterpolationConfig>,
+    server_tick: Option<Res<'w, ServerTick>>,
+}
+
+impl PredictedInputWriter<'_, '_> {
+    pub fn trigger<E: Event + Clone + Serialize>(&mut self, event: E) {
+        if self.client.is_connected() {
+            let tick = self.predicted_time.elapsed_secs_f64() * self.config.max_tick_rate as f64;
+            let input = PredictedInput {
+                tick: tick as u32,
+                delta_time: self.predicted_time.delta_secs(),
+                event,
+            };
+            self.commands.trigger(input.clone());
+            self.commands.client_trigger(input);
+        } else {
+            self.commands.client_trigger(PredictedInput {
+                tick: self.server_tick.as_ref().map_or(0, |tick| tick.get()),
+                delta_time: self.time.delta_secs(),
+                event,
+            });
+        }
+    }
+}
+
 #[derive(Component, Deserialize, Serialize, Reflect, Default)]
 pub struct OwnerPredicted;
 
-#[derive(Component, Reflect)]
+/// Replicated prediction policy of an entity.
+///
+/// Each client turns it into local `Predicted` or `Interpolated` markers.
+/// `OwnerPredicted` is equivalent to `PredictionMode::Owner`.
+#[derive(Component, Deserialize, Serialize, Reflect, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PredictionMode {
+    /// The owning client predicts, all other clients interpolate.
+    Owner,
+    /// Every client predicts.
+    AllClients,
+    /// Every client interpolates.
+    InterpolatedOnly,
+    /// Server values are applied as they arrive, without prediction or interpolation.
+    Authoritative,
+}
+
+#[derive(Component, Reflect, Default)]
 pub struct Predicted;
 
+/// Server tick from which the local client started predicting an entity.
+///
+/// Inputs recorded before this tick are not replayed onto the entity.
+#[derive(Component, Reflect, Default)]
+pub struct PredictedSince(pub u32);
+
+/// Blends a component from the value that was displayed before the local client
+/// became the owner into the predicted value.
+#[derive(Component)]
+pub struct PredictionHandoff<C: Component + Interpolate + Clone> {
+    pub from: C,
+    pub target: C,
+    pub elapsed: f32,
+}
+
+/// Values the local client predicted for an entity, keyed by the tick of the input
+/// that produced them.
+#[derive(Component)]
+pub struct PredictionHistory<C: Component> {
+    pub values: VecDeque<(u32, C)>,
+}
+
+impl<C: Component> Default for PredictionHistory<C> {
+    fn default() -> Self {
+        Self {
+            values: VecDeque::new(),
+        }
+    }
+}
+
+impl<C: Component> PredictionHistory<C> {
+    /// Records a predicted value and drops values the server has already confirmed.
+    pub fn insert(&mut self, tick: u32, value: C, server_tick: u32) {
+        // Keep the latest value before the server tick, it is the baseline to compare against
+        while self.values.len() > 1 && self.values[1].0 < server_tick {
+            self.values.pop_front();
+        }
+        if self.values.len() >= 64 {
+            self.values.pop_front();
+        }
+        self.values.push_back((tick, value));
+    }
+
+    /// The predicted value after all inputs before `server_tick` were applied.
+    pub fn at(&self, server_tick: u32) -> Option<&C> {
+        self.values
+            .iter()
+            .rev()
+            .find(|(tick, _)| *tick < server_tick)
+            .map(|(_, value)| value)
+    }
+
+    /// Whether the value at `server_tick` is known: an older value was kept and inputs were
+    /// recorded up to the tick.
+    pub fn covers(&self, server_tick: u32) -> bool {
+        match (self.values.front(), self.values.back()) {
+            (Some(&(first, _)), Some(&(last, _))) => first < server_tick && last + 1 >= server_tick,
+            _ => false,
+        }
+    }
+}
+
+/// How far apart two predicted values are, used to skip and measure corrections.
+///
+/// Derived by `#[derive(Interpolate)]` with `#[interpolate(distance)]`.
+pub trait PredictionDistance {
+    fn prediction_distance(&self, other: &Self) -> f32;
+}
+
+/// Largest `PredictionDistance` between the server and the predicted value of `C` at which
+/// no correction is applied. Without it every server update is replayed and corrections
+/// are not measured.
+#[derive(Resource)]
+pub struct PredictionTolerance<C> {
+    pub tolerance: f32,
+    pub distance: fn(&C, &C) -> f32,
+}
+
+impl<C: PredictionDistance> PredictionTolerance<C> {
+    pub fn new(tolerance: f32) -> Self {
+        Self {
+            tolerance,
+            distance: C::prediction_distance,
+        }
+    }
+}
+
+#[derive(Resource, Serialize, Deserialize, Debug)]
+pub struct OwnershipHandoffConfig {
+    /// Seconds to blend into prediction after gaining ownership of an entity.
+    pub blend_duration: f32,
+}
+
+impl Default for OwnershipHandoffConfig {
+    fn default() -> Self {
+        Self {
+            blend_duration: 0.1,
+        }
+    }
+}
+
 impl<T: Event> PredictedEventHistory<T> {
     pub fn new() -> PredictedEventHistory<T> {
         Self(VecDeque::new())
@@ -79,17 +288,162 @@ impl<T: Event> PredictedEventHistory<T> {
     }
 }
 
-pub fn owner_prediction_init_system(
-    trigger: Trigger<OnAdd, OwnerPredicted>,
-    q_owners: Query<(Entity, &NetworkId)>,
-    // client: Res<RepliconClient>,
+/// Turns `PredictionMode` and `OwnerPredicted` into local `Predicted` and `Interpolated` markers.
+///
+/// Runs whenever one of them or `NetworkOwner` is inserted, so ownership and mode can change
+/// at runtime.
+/// Owner predicted entities that arrive before the `LocalClient` is known are resolved by
+/// `local_client_init_system`.
+pub fn prediction_mode_init_system(
+    trigger: Trigger<OnInsert, (OwnerPredicted, PredictionMode, NetworkOwner)>,
+    q_subjects: Query<PredictionSubject, Or<(With<OwnerPredicted>, With<PredictionMode>)>>,
+    local_client: Option<Res<LocalClient>>,
+    spectator: Option<Res<SpectatorClock>>,
+    client: Res<RepliconClient>,
     mut commands: Commands,
 ) {
-    for (e, _) in q_owners.iter() {
-        if e == trigger.target() {
-            commands.entity(e).insert(Predicted);
+    if !client.is_connected() {
+        return;
+    }
+    if let Ok(subject) = q_subjects.get(trigger.target()) {
+        let spectating = spectator.is_some();
+        apply_prediction_mode(&mut commands, subject, local_client.as_deref(), spectating);
+    }
+}
+
+/// Stores the identity sent by the server and resolves all owner predicted entities against it.
+pub fn local_client_init_system(
+    trigger: Trigger<LocalClientIdentity>,
+    q_subjects: Query<PredictionSubject, Or<(With<OwnerPredicted>, With<PredictionMode>)>>,
+    spectator: Option<Res<SpectatorClock>>,
+    mut commands: Commands,
+) {
+    let local_client = LocalClient(trigger.event().0);
+    commands.insert_resource(local_client);
+    for subject in q_subjects.iter() {
+        let spectating = spectator.is_some();
+        apply_prediction_mode(&mut commands, subject, Some(&local_client), spectating);
+    }
+}
+
+/// Sends every newly authorized client the entity that represents it on the server.
+///
+/// Waits for `AuthorizedClient`, because events to clients that have not passed the protocol
+/// check are dropped.
+pub fn server_local_client_system(
+    trigger: Trigger<OnAdd, AuthorizedClient>,
+    mut commands: Commands,
+) {
+    commands.server_trigger(ToClients {
+        mode: SendMode::Direct(trigger.target()),
+        event: LocalClientIdentity(trigger.target()),
+    });
+}
+
+/// Forgets the local identity after disconnecting.
+pub fn local_client_cleanup_system(mut commands: Commands) {
+    commands.remove_resource::<LocalClient>();
+}
+
+/// Components that decide which local markers an entity gets.
+#[derive(QueryData)]
+pub struct PredictionSubject {
+    entity: Entity,
+    mode: Option<&'static PredictionMode>,
+    owner: Option<&'static NetworkOwner>,
+    owner_predicted: Has<OwnerPredicted>,
+    predicted: Has<Predicted>,
+    interpolated: Has<Interpolated>,
+    confirmed: Option<&'static ConfirmHistory>,
+}
+
+fn apply_prediction_mode(
+    commands: &mut Commands,
+    subject: PredictionSubjectItem,
+    local_client: Option<&LocalClient>,
+    spectating: bool,
+) {
+    let mode = match (subject.mode, subject.owner_predicted) {
+        (Some(mode), _) => *mode,
+        (None, true) => PredictionMode::Owner,
+        (None, false) => return,
+    };
+    let predict = match mode {
+        // Spectators only watch, so nothing is predicted
+        PredictionMode::Authoritative => None,
+        _ if spectating => Some(false),
+        PredictionMode::Owner => {
+            // Wait for the local identity before deciding
+            let (Some(owner), Some(local_client)) = (subject.owner, local_client) else {
+                return;
+            };
+            Some(owner.0 == local_client.0)
+        }
+        PredictionMode::AllClients => Some(true),
+        PredictionMode::InterpolatedOnly => Some(false),
+    };
+
+    let mut entity = commands.entity(subject.entity);
+    match predict {
+        Some(true) if !subject.predicted => {
+            let since = subject.confirmed.map_or(0, |c| c.last_tick().get());
+            entity
+                .remove::<Interpolated>()
+                .insert((Predicted, PredictedSince(since)));
+        }
+        Some(false) if !subject.interpolated => {
+            entity
+                .remove::<(Predicted, PredictedSince)>()
+                .insert(Interpolated);
+        }
+        None => {
+            entity.remove::<(Predicted, PredictedSince, Interpolated, RecordSnapshotsMarker)>();
+        }
+        _ => {}
+    }
+}
+
+/// Starts blending from the displayed value into prediction when an entity that already
+/// has snapshots becomes predicted, e.g. after an ownership change.
+pub fn prediction_handoff_init_system<C: Component + Interpolate + Clone>(
+    trigger: Trigger<OnAdd, Predicted>,
+    q: Query<(&C, &SnapshotBuffer<C>)>,
+    mut commands: Commands,
+) {
+    let Ok((component, snapshot_buffer)) = q.get(trigger.target()) else {
+        return;
+    };
+    if snapshot_buffer.buffer.is_empty() {
+        return;
+    }
+    commands.entity(trigger.target()).insert(PredictionHandoff {
+        from: component.clone(),
+        target: snapshot_buffer.latest_snapshot(),
+        elapsed: 0.0,
+    });
+}
+
+/// Blends handed off entities towards their predicted value.
+pub fn prediction_handoff_system<C: Component<Mutability=Mutable> + Interpolate + Clone>(
+    mut q: Query<(Entity, &mut C, &mut PredictionHandoff<C>, Has<Predicted>)>,
+    time: Res<Time>,
+    config: Res<OwnershipHandoffConfig>,
+    mut commands: Commands,
+) {
+    for (e, mut component, mut handoff, predicted) in q.iter_mut() {
+        if !predicted {
+            commands.entity(e).remove::<PredictionHandoff<C>>();
+            continue;
+        }
+        handoff.elapsed += time.delta_secs();
+        let t = if config.blend_duration > 0.0 {
+            (handoff.elapsed / config.blend_duration).clamp(0., 1.)
         } else {
-            commands.entity(e).insert(Interpolated);
+            1.0
+        };
+        *component = handoff.from.interpolate(handoff.target.clone(), t);
+        if t >= 1.0 {
+            commands.entity(e).remove::<PredictionHandoff<C>>();
         }
     }
 }
@@ -104,20 +458,106 @@ pub fn predicted_snapshot_system<T: Component + Interpolate + Clone>(
     }
 }
 
+/// Prepares the input buffer of a newly authorized client.
+pub fn server_input_buffer_init_system<E: Event>(
+    trigger: Trigger<OnAdd, AuthorizedClient>,
+    mut commands: Commands,
+) {
+    commands
+        .entity(trigger.target())
+        .insert(InputBuffer::<E>::default());
+}
+
+/// Buffers the inputs of clients until the server reaches their tick and measures how far
+/// ahead they arrive. Inputs of the server itself are applied right away.
+pub fn server_input_receive_system<E: Event + Clone>(
+    trigger: Trigger<FromClient<PredictedInput<E>>>,
+    mut q_clients: Query<(&mut InputBuffer<E>, Option<&InputLead>)>,
+    server_tick: Res<ServerTick>,
+    dilation: Option<Res<TimeDilation>>,
+    mut commands: Commands,
+) {
+    let client_entity = trigger.client_entity;
+    let mut input = trigger.event.clone();
+    input.delta_time = input.delta_time.clamp(0.0, MAX_INPUT_DELTA);
+    if client_entity == SERVER {
+        commands.trigger(ServerInput {
+            client_entity,
+            input,
+        });
+        return;
+    }
+
+    let Ok((mut buffer, lead)) = q_clients.get_mut(client_entity) else {
+        return;
+    };
+    let server_tick = server_tick.get();
+    if dilation.is_some() {
+        commands
+            .entity(client_entity)
+            .insert(InputLead::measure(lead, input.tick, server_tick));
+    }
+    input.tick = input.tick.min(server_tick + MAX_INPUT_LEAD);
+    buffer.insert(input);
+}
+
+/// Applies inputs that were sent as plain `E` with `client_trigger` as soon as they arrive,
+/// stamped with the current tick. They are not predicted on the client.
+pub fn server_unstamped_input_system<E: Event + Clone>(
+    trigger: Trigger<FromClient<E>>,
+    server_tick: Res<ServerTick>,
+    time: Res<Time>,
+    mut commands: Commands,
+) {
+    commands.trigger(ServerInput {
+        client_entity: trigger.client_entity,
+        input: PredictedInput {
+            tick: server_tick.get(),
+            delta_time: time.delta_secs().min(MAX_INPUT_DELTA),
+            event: trigger.event.clone(),
+        },
+    });
+}
+
+/// Applies buffered inputs whose tick the server has reached.
+pub fn server_input_release_system<E: Event + Clone>(
+    mut q_clients: Query<(Entity, &mut InputBuffer<E>)>,
+    server_tick: Res<ServerTick>,
+    mut commands: Commands,
+) {
+    let server_tick = server_tick.get();
+    for (client_entity, mut buffer) in q_clients.iter_mut() {
+        let due = buffer
+            .inputs
+            .iter()
+            .take_while(|input| input.tick <= server_tick)
+            .count();
+        if due == 0 {
+            continue;
+        }
+        for input in buffer.inputs.drain(..due) {
+            commands.trigger(ServerInput {
+                client_entity,
+                input,
+            });
+        }
+    }
+}
+
 /// Server implementation
 pub fn server_update_system<
     E: Event,
     T: Component,
-    C: Component<Mutability=Mutable> + Interpolate + Predict<E, T> + Clone,
+    C: Component<Mutability=Mutable> + Predict<E, T>,
 >(
-    trigger: Trigger<FromClient<E>>,
-    time: Res<Time>,
+    trigger: Trigger<ServerInput<E>>,
     mut subjects: Query<(&NetworkOwner, &mut C, &T), Without<Predicted>>,
 ) {
+    let input = &trigger.input;
     for (player, mut component, context) in &mut subjects {
         if trigger.client_entity == player.0 {
             println!("Server");
-            component.apply_event(trigger.event(), time.delta_secs(), context);
+            component.apply_event(&input.event, input.delta_time, context);
         }
     }
 }
@@ -128,34 +568,114 @@ pub fn predicted_update_system<
     T: Component,
     C: Component<Mutability=Mutable> + Interpolate + Predict<E, T> + Clone,
 >(
-    local_events: Trigger<FromClient<E>>,
+    input: Trigger<PredictedInput<E>>,
     mut q_predicted_players: Query<
-        (&mut C, &SnapshotBuffer<C>, &ConfirmHistory, &T),
+        (
+            Entity,
+            &mut C,
+            &SnapshotBuffer<C>,
+            &T,
+            Option<&PredictedSince>,
+            Option<&mut PredictionHandoff<C>>,
+            Option<&mut PredictionHistory<C>>,
+        ),
         (With<Predicted>, Without<Interpolated>),
     >,
     mut event_history: ResMut<PredictedEventHistory<E>>,
-    time: Res<Time>,
+    mut stats: Option<ResMut<PredictionStats<C>>>,
+    tolerance: Option<Res<PredictionTolerance<C>>>,
+    desync_detection: Option<Res<DesyncDetection<C>>>,
+    mut commands: Commands,
 ) {
-    // Apply all pending inputs to latest snapshot
-    for (mut component, snapshot_buffer, confirmed, context) in q_predicted_players.iter_mut() {
-        // Append the latest input event
-        event_history.insert(
-            local_events.event.clone(),
-            confirmed.last_tick().get(),
-            time.delta_secs(),
-        );
+    if q_predicted_players.is_empty() {
+        return;
+    }
+    // Append the latest input event once, no matter how many entities predict it
+    let PredictedInput {
+        tick,
+        delta_time,
+        ref event,
+    } = *input.event();
+    event_history.insert(event.clone(), tick, delta_time);
+
+    for (e, mut component, snapshot_buffer, context, since, handoff, values) in
+        q_predicted_players.iter_mut()
+    {
+        let server_tick = snapshot_buffer.latest_snapshot_tick();
+
+        // What we would have predicted without hearing from the server
+        let mut predicted = handoff
+            .as_ref()
+            .map_or(component.clone(), |h| h.target.clone());
+        predicted.apply_event(event, delta_time, context);
+
+        // Skip the replay if our prediction at the server tick was close enough
+        let within_tolerance = tolerance.as_ref().is_some_and(|tolerance| {
+            values
+                .as_ref()
+                .and_then(|values| values.at(server_tick))
+                .is_some_and(|value| {
+                    (tolerance.distance)(value, &snapshot_buffer.latest_snapshot())
+                        <= tolerance.tolerance
+                })
+        });
+
+        let value = if within_tolerance {
+            event_history.remove_stale(server_tick);
+            predicted
+        } else {
+            // Apply all pending inputs to latest snapshot
+            let since = since.map_or(0, |s| s.0);
+            let mut corrected_component = snapshot_buffer.latest_snapshot();
+            let mut replayed_inputs = 0;
+            for event_snapshot in event_history
+                .predict(server_tick)
+                .filter(|e| e.tick >= since)
+            {
+                corrected_component.apply_event(
+                    &event_snapshot.value,
+                    event_snapshot.delta_time,
+                    context,
+                );
+                replayed_inputs += 1;
+            }
 
+            // Corrections can only be measured with a distance
+            let distance = tolerance
+                .as_ref()
+                .map(|tolerance| (tolerance.distance)(&predicted, &corrected_component));
+            if let (Some(stats), Some(distance)) = (stats.as_mut(), distance) {
+                stats.record_correction(distance);
+            }
+            if distance.is_some_and(|distance| distance > 0.0) {
+                commands.trigger_targets(
+                    Mispredicted {
+                        entity: e,
+                        predicted,
+                        corrected: corrected_component.clone(),
+                        replayed_inputs,
+                    },
+                    e,
+                );
+            }
+            corrected_component
+        };
 
-        let mut corrected_component = snapshot_buffer.latest_snapshot();
-        for event_snapshot in event_history.predict(snapshot_buffer.latest_snapshot_tick()) {
-            corrected_component.apply_event(
-                &event_snapshot.value,
-                event_snapshot.delta_time,
-                context,
-            );
+        // Predicted values are compared against the server by tolerance and desync detection
+        if tolerance.is_some() || desync_detection.is_some() {
+            match values {
+                Some(mut values) => values.insert(tick, value.clone(), server_tick),
+                None => {
+                    let mut values = PredictionHistory::default();
+                    values.insert(tick, value.clone(), server_tick);
+                    commands.entity(e).insert(values);
+                }
+            }
+        }
+        match handoff {
+            Some(mut handoff) => handoff.target = value,
+            None => *component = value,
         }
-        println!("C");
-        *component = corrected_component;
     }
 }
 
@@ -170,32 +690,205 @@ pub trait AppPredictionExt {
     /// This will generate serverside and clientside systems that use the implementation from the
     /// `Predict` trait to allow prediction and serverside correction
     fn predict_event_for_component<E, T, C>(&mut self) -> &mut Self
+    where
+        E: Event + Serialize + DeserializeOwned + Debug + Clone,
+        T: Component<Mutability=Mutable> + Serialize + DeserializeOwned,
+        C: Component<Mutability=Mutable> + Interpolate + Predict<E, T> + Clone;
+
+    /// Register a component to be replicated and predicted without interpolation,
+    /// for discrete state like counters, flags or enums.
+    /// Received values of predicted entities are stored as `Confirmed<C>`.
+    fn replicate_predicted<C>(&mut self) -> &mut Self
+    where
+        C: Component<Mutability=Mutable> + Clone + Serialize + DeserializeOwned;
+
+    /// Register a component registered with `replicate_predicted` and event pair for prediction.
+    /// Pending events are replayed on top of every confirmed value.
+    fn predict_discrete_event_for_component<E, T, C>(&mut self) -> &mut Self
     where
         E: Event + Serialize + DeserializeOwned + Debug + Clone,
         T: Component<Mutability=Mutable> + Serialize + DeserializeOwned,
         C: Component<Mutability=Mutable> + Predict<E, T> + Clone;
+
+    /// Register a resource and event pair for prediction.
+    /// The server applies the events of all clients to its resource and sends it to the clients
+    /// whenever it changes, clients replay their pending events on top of every server value.
+    fn predict_event_for_resource<E, R>(&mut self) -> &mut Self
+    where
+        E: Event + Serialize + DeserializeOwned + Debug + Clone,
+        R: PredictResource<E> + Clone + Serialize + DeserializeOwned;
+
+    /// Skip corrections of a predicted component while the server value at the confirmed tick
+    /// is within `tolerance` of the predicted one, as measured by `PredictionDistance`.
+    /// Corrections are measured for `Mispredicted` and the diagnostics, so a tolerance of
+    /// `0.0` only enables the measurements.
+    fn set_prediction_tolerance<C>(&mut self, tolerance: f32) -> &mut Self
+    where
+        C: Component + PredictionDistance;
 }
 
 impl AppPredictionExt for App {
+    fn set_prediction_tolerance<C>(&mut self, tolerance: f32) -> &mut Self
+    where
+        C: Component + PredictionDistance,
+    {
+        self.insert_resource(PredictionTolerance::<C>::new(tolerance))
+    }
+
     fn add_client_predicted_event<E>(&mut self, channel: Channel) -> &mut Self
     where
         E: Event + Serialize + DeserializeOwned + Debug + Clone,
     {
         let history: PredictedEventHistory<E> = PredictedEventHistory::new();
         self.insert_resource(history);
-        self.add_client_trigger::<E>(channel)
+        self.add_client_trigger::<PredictedInput<E>>(channel)
+            .add_client_trigger::<E>(channel)
+            .add_observer(server_input_buffer_init_system::<E>)
+            .add_observer(server_input_receive_system::<E>)
+            .add_observer(server_unstamped_input_system::<E>)
+            .add_systems(
+                PreUpdate,
+                server_input_release_system::<E>
+                    .after(ServerSet::Receive)
+                    .run_if(server_running),
+            )
     }
 
     fn predict_event_for_component<E, T, C>(&mut self) -> &mut Self
     where
         E: Event + Serialize + DeserializeOwned + Debug + Clone,
         T: Component<Mutability=Mutable> + Serialize + DeserializeOwned,
-        C: Component<Mutability=Mutable> + Predict<E, T> + Clone,
+        C: Component<Mutability=Mutable> + Interpolate + Predict<E, T> + Clone,
     {
+        register_prediction_diagnostics::<C>(self);
         self.add_observer(
             predicted_update_system::<E, T, C>
         )
             .add_observer(server_update_system::<E, T, C>)
             .replicate::<T>()
     }
+
+    fn replicate_predicted<C>(&mut self) -> &mut Self
+    where
+        C: Component<Mutability=Mutable> + Clone + Serialize + DeserializeOwned,
+    {
+        register_discrete_prediction::<C>(self)
+    }
+
+    fn predict_discrete_event_for_component<E, T, C>(&mut self) -> &mut Self
+    where
+        E: Event + Serialize + DeserializeOwned + Debug + Clone,
+        T: Component<Mutability=Mutable> + Serialize + DeserializeOwned,
+        C: Component<Mutability=Mutable> + Predict<E, T> + Clone,
+    {
+        register_discrete_event::<E, T, C>(self)
+            .add_observer(server_update_system::<E, T, C>)
+            .replicate::<T>()
+    }
+
+    fn predict_event_for_resource<E, R>(&mut self) -> &mut Self
+    where
+        E: Event + Serialize + DeserializeOwned + Debug + Clone,
+        R: PredictResource<E> + Clone + Serialize + DeserializeOwned,
+    {
+        register_predicted_resource::<E, R>(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::connected_apps;
+
+    #[derive(Event, Deserialize, Serialize, Clone, Debug)]
+    struct Jump(u32);
+
+    /// Inputs applied by the server with the server tick they were applied at.
+    #[derive(Resource, Default)]
+    struct Applied(Vec<(u32, PredictedInput<Jump>)>);
+
+    fn input_app() -> (App, Entity) {
+        let (mut server_app, _client_app, client_entity) = connected_apps(|app| {
+            app.add_client_predicted_event::<Jump>(Channel::Ordered);
+        });
+        server_app.init_resource::<Applied>().add_observer(
+            |trigger: Trigger<ServerInput<Jump>>,
+             server_tick: Res<ServerTick>,
+             mut applied: ResMut<Applied>| {
+                applied.0.push((server_tick.get(), trigger.input.clone()));
+            },
+        );
+        (server_app, client_entity)
+    }
+
+    fn send(app: &mut App, client_entity: Entity, id: u32, tick: u32, delta_time: f32) {
+        app.world_mut().trigger(FromClient {
+            client_entity,
+            event: PredictedInput {
+                tick,
+                delta_time,
+                event: Jump(id),
+            },
+        });
+    }
+
+    #[test]
+    fn inputs_are_clamped_and_released_in_tick_order() {
+        let (mut app, client_entity) = input_app();
+        let server_tick = app.world().resource::<ServerTick>().get();
+        send(&mut app, client_entity, 0, server_tick + 2, 1.0);
+        send(&mut app, client_entity, 1, server_tick + 1, 0.1);
+        send(&mut app, client_entity, 2, server_tick + 1000, 0.1);
+
+        let buffer = app.world().get::<InputBuffer<Jump>>(client_entity).unwrap();
+        let buffered: Vec<_> = buffer
+            .inputs
+            .iter()
+            .map(|input| (input.event.0, input.tick, input.delta_time))
+            .collect();
+        assert_eq!(
+            buffered,
+            [
+                (1, server_tick + 1, 0.1),
+                (0, server_tick + 2, MAX_INPUT_DELTA),
+                (2, server_tick + MAX_INPUT_LEAD, 0.1),
+            ]
+        );
+        assert!(app.world().resource::<Applied>().0.is_empty());
+
+        for _ in 0..10 {
+            if app.world().resource::<Applied>().0.len() >= 2 {
+                break;
+            }
+            app.update();
+        }
+
+        let applied = &app.world().resource::<Applied>().0;
+        let ids: Vec<_> = applied.iter().map(|(_, input)| input.event.0).collect();
+        assert_eq!(ids, [1, 0]);
+        for (applied_at, input) in applied {
+            assert!(input.tick <= *applied_at);
+        }
+        let buffer = app.world().get::<InputBuffer<Jump>>(client_entity).unwrap();
+        assert_eq!(buffer.inputs.len(), 1);
+        assert_eq!(buffer.inputs[0].event.0, 2);
+    }
+
+    #[test]
+    fn server_and_plain_inputs_are_applied_on_arrival() {
+        let (mut app, client_entity) = input_app();
+        let server_tick = app.world().resource::<ServerTick>().get();
+        send(&mut app, SERVER, 0, server_tick + 5, 1.0);
+        app.world_mut().trigger(FromClient {
+            client_entity,
+            event: Jump(1),
+        });
+
+        let applied = &app.world().resource::<Applied>().0;
+        assert_eq!(applied.len(), 2);
+        assert_eq!(applied[0].1.event.0, 0);
+        assert_eq!(applied[0].1.delta_time, MAX_INPUT_DELTA);
+        assert_eq!(applied[1].1.event.0, 1);
+        assert_eq!(applied[1].1.tick, server_tick);
+    }
 }
@@ -0,0 +1,85 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// How values between two snapshots are computed.
+#[derive(Deserialize, Serialize, Reflect, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum InterpolationCurve {
+    #[default]
+    Linear,
+    /// Eases in and out of every snapshot.
+    SmoothStep,
+    /// Jumps to every snapshot as soon as it arrives.
+    Step,
+}
+
+impl InterpolationCurve {
+    /// Maps the linear progress between two snapshots onto the curve.
+    pub fn apply(&self, t: f32) -> f32 {
+        match self {
+            InterpolationCurve::Linear => t,
+            InterpolationCurve::SmoothStep => {
+                let t = t.clamp(0.0, 1.0);
+                t * t * (3.0 - 2.0 * t)
+            }
+            InterpolationCurve::Step => 1.0,
+        }
+    }
+}
+
+/// Interpolation settings for a kind of entity.
+#[derive(Deserialize, Serialize, Reflect, Clone, Debug)]
+pub struct InterpolationProfile {
+    /// Server ticks the displayed value lags behind the latest snapshot interval.
+    pub delay: f32,
+    /// Number of buffered snapshots.
+    pub buffer_len: usize,
+    /// Seconds the displayed value may be extrapolated past the latest snapshot.
+    pub max_extrapolation: f32,
+    pub curve: InterpolationCurve,
+}
+
+impl Default for InterpolationProfile {
+    fn default() -> Self {
+        Self {
+            delay: 0.0,
+            buffer_len: 10,
+            max_extrapolation: 0.0,
+            curve: InterpolationCurve::Linear,
+        }
+    }
+}
+
+/// Selects a profile from `InterpolationProfiles` for an entity.
+///
+/// Replicated, so the server can choose the profile of the entities it spawns.
+#[derive(Component, Deserialize, Serialize, Reflect, Clone, Debug, PartialEq, Eq)]
+pub struct InterpolationProfileName(pub String);
+
+impl InterpolationProfileName {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self(name.into())
+    }
+}
+
+/// Named interpolation profiles.
+///
+/// Entities without an `InterpolationProfileName` or with an unknown name use `default`.
+#[derive(Resource, Default, Debug)]
+pub struct InterpolationProfiles {
+    pub default: InterpolationProfile,
+    pub profiles: HashMap<String, InterpolationProfile>,
+}
+
+impl InterpolationProfiles {
+    pub fn insert(&mut self, name: impl Into<String>, profile: InterpolationProfile) -> &mut Self {
+        self.profiles.insert(name.into(), profile);
+        self
+    }
+
+    /// The profile selected by `name`.
+    pub fn get(&self, name: Option<&InterpolationProfileName>) -> &InterpolationProfile {
+        name.and_then(|name| self.profiles.get(&name.0)).unwrap_or(&self.default)
+    }
+}
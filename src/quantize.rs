@@ -0,0 +1,285 @@
+use std::f32::consts::FRAC_1_SQRT_2;
+
+use bevy::prelude::*;
+use bevy_replicon::{
+    bytes::Buf,
+    shared::replication::replication_registry::{
+        ctx::{SerializeCtx, WriteCtx},
+        rule_fns::RuleFns,
+    },
+};
+
+pub use bevy_replicon::bytes::Bytes;
+
+/// Compact wire encoding used instead of serde for replicated components.
+///
+/// Can be derived with `#[derive(Quantize)]` and a `#[quantize(min = .., max = .., bits = ..)]`
+/// attribute on the struct or on individual fields.
+pub trait Quantize: Sized {
+    fn quantize(&self, message: &mut Vec<u8>);
+    fn dequantize(cursor: &mut Bytes) -> Result<Self>;
+}
+
+/// A value that can be encoded with a `FixedPoint` range.
+pub trait QuantizeField: Sized {
+    fn write_quantized(&self, fixed: FixedPoint, message: &mut Vec<u8>);
+    fn read_quantized(fixed: FixedPoint, cursor: &mut Bytes) -> Result<Self>;
+}
+
+/// Fixed-point encoding of floats within `min..=max` using `bits` bits.
+///
+/// Values are not bit-packed but sent in `bits` rounded up to whole bytes, so `bits = 10` costs
+/// as much as `bits = 16`. Only multiples of 8 save bandwidth over a smaller `bits`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FixedPoint {
+    pub min: f32,
+    pub max: f32,
+    pub bits: u8,
+}
+
+impl FixedPoint {
+    pub const fn new(min: f32, max: f32, bits: u8) -> Self {
+        assert!(bits > 0 && bits <= 32, "bits should be in range 1..=32");
+        assert!(min < max, "min should be less than max");
+        Self { min, max, bits }
+    }
+
+    /// Largest error introduced by the encoding for values within the range.
+    pub fn precision(&self) -> f32 {
+        let step = (self.max as f64 - self.min as f64) / self.steps() as f64;
+        // Decoded values are rounded to the nearest `f32` as well
+        let rounding = self.min.abs().max(self.max.abs()) as f64 * f32::EPSILON as f64;
+        (step / 2.0 + rounding) as f32
+    }
+
+    /// Values outside of the range are clamped to it.
+    pub fn encode(&self, value: f32) -> u32 {
+        let (min, max) = (self.min as f64, self.max as f64);
+        let normalized = ((value as f64 - min) / (max - min)).clamp(0.0, 1.0);
+        (normalized * self.steps() as f64).round() as u32
+    }
+
+    pub fn decode(&self, raw: u32) -> f32 {
+        let normalized = raw.min(self.steps()) as f64 / self.steps() as f64;
+        (self.min as f64 + (self.max as f64 - self.min as f64) * normalized) as f32
+    }
+
+    pub fn write(&self, value: f32, message: &mut Vec<u8>) {
+        let bytes = self.encode(value).to_le_bytes();
+        message.extend_from_slice(&bytes[..self.byte_len()]);
+    }
+
+    pub fn read(&self, cursor: &mut Bytes) -> Result<f32> {
+        let len = self.byte_len();
+        if cursor.remaining() < len {
+            return Err("not enough bytes to read a quantized value".into());
+        }
+        let mut bytes = [0; 4];
+        cursor.copy_to_slice(&mut bytes[..len]);
+        Ok(self.decode(u32::from_le_bytes(bytes)))
+    }
+
+    fn steps(&self) -> u32 {
+        if self.bits >= 32 {
+            u32::MAX
+        } else {
+            (1 << self.bits) - 1
+        }
+    }
+
+    fn byte_len(&self) -> usize {
+        self.bits.div_ceil(8) as usize
+    }
+}
+
+impl QuantizeField for f32 {
+    fn write_quantized(&self, fixed: FixedPoint, message: &mut Vec<u8>) {
+        fixed.write(*self, message);
+    }
+
+    fn read_quantized(fixed: FixedPoint, cursor: &mut Bytes) -> Result<Self> {
+        fixed.read(cursor)
+    }
+}
+
+impl QuantizeField for Vec2 {
+    fn write_quantized(&self, fixed: FixedPoint, message: &mut Vec<u8>) {
+        fixed.write(self.x, message);
+        fixed.write(self.y, message);
+    }
+
+    fn read_quantized(fixed: FixedPoint, cursor: &mut Bytes) -> Result<Self> {
+        Ok(Vec2::new(fixed.read(cursor)?, fixed.read(cursor)?))
+    }
+}
+
+impl QuantizeField for Vec3 {
+    fn write_quantized(&self, fixed: FixedPoint, message: &mut Vec<u8>) {
+        fixed.write(self.x, message);
+        fixed.write(self.y, message);
+        fixed.write(self.z, message);
+    }
+
+    fn read_quantized(fixed: FixedPoint, cursor: &mut Bytes) -> Result<Self> {
+        Ok(Vec3::new(
+            fixed.read(cursor)?,
+            fixed.read(cursor)?,
+            fixed.read(cursor)?,
+        ))
+    }
+}
+
+/// Smallest-three encoding: the index of the largest component is sent in one byte and the other
+/// three are sent with `fixed.bits` each. The range of `fixed` is ignored.
+impl QuantizeField for Quat {
+    fn write_quantized(&self, fixed: FixedPoint, message: &mut Vec<u8>) {
+        let mut components = self.normalize().to_array();
+        let largest = (0..4)
+            .max_by(|&a, &b| components[a].abs().total_cmp(&components[b].abs()))
+            .unwrap_or_default();
+        // `q` and `-q` are the same rotation, so the largest component can always be positive
+        if components[largest] < 0.0 {
+            components = components.map(|c| -c);
+        }
+
+        let range = smallest_three_range(fixed.bits);
+        message.push(largest as u8);
+        for (index, component) in components.into_iter().enumerate() {
+            if index != largest {
+                range.write(component, message);
+            }
+        }
+    }
+
+    fn read_quantized(fixed: FixedPoint, cursor: &mut Bytes) -> Result<Self> {
+        if !cursor.has_remaining() {
+            return Err("not enough bytes to read a quantized rotation".into());
+        }
+        let largest = cursor.get_u8() as usize;
+        if largest > 3 {
+            return Err(format!("invalid largest component index {largest}").into());
+        }
+
+        let range = smallest_three_range(fixed.bits);
+        let mut components = [0.0; 4];
+        let mut sum = 0.0;
+        for (index, component) in components.iter_mut().enumerate() {
+            if index != largest {
+                *component = range.read(cursor)?;
+                sum += *component * *component;
+            }
+        }
+        components[largest] = (1.0 - sum).max(0.0).sqrt();
+
+        Ok(Quat::from_array(components).normalize())
+    }
+}
+
+fn smallest_three_range(bits: u8) -> FixedPoint {
+    FixedPoint::new(-FRAC_1_SQRT_2, FRAC_1_SQRT_2, bits)
+}
+
+pub fn serialize_quantized<C: Quantize>(
+    _ctx: &SerializeCtx,
+    component: &C,
+    message: &mut Vec<u8>,
+) -> Result<()> {
+    component.quantize(message);
+    Ok(())
+}
+
+pub fn deserialize_quantized<C: Quantize>(
+    _ctx: &mut WriteCtx,
+    cursor: &mut Bytes,
+) -> Result<C> {
+    C::dequantize(cursor)
+}
+
+/// Serialization functions that use the `Quantize` implementation of a component.
+pub fn quantized_rule_fns<C: Component + Quantize>() -> RuleFns<C> {
+    RuleFns::new(serialize_quantized::<C>, deserialize_quantized::<C>)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip<T: QuantizeField>(value: &T, fixed: FixedPoint) -> (T, usize) {
+        let mut message = Vec::new();
+        value.write_quantized(fixed, &mut message);
+        let len = message.len();
+        let mut cursor = Bytes::from(message);
+        let decoded = T::read_quantized(fixed, &mut cursor).unwrap();
+        assert!(!cursor.has_remaining());
+        (decoded, len)
+    }
+
+    #[test]
+    fn fixed_point_round_trip() {
+        for bits in [1, 8, 10, 16, 17, 24, 31, 32] {
+            let fixed = FixedPoint::new(-1000.0, 1000.0, bits);
+            for value in [-1000.0, -999.9, -0.1, 0.0, 0.3, 123.456, 999.99, 1000.0] {
+                let (decoded, len) = round_trip(&value, fixed);
+                assert_eq!(len, bits.div_ceil(8) as usize);
+                assert!(
+                    (decoded - value).abs() <= fixed.precision(),
+                    "{value} decoded as {decoded} with {bits} bits"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn fixed_point_clamps() {
+        let fixed = FixedPoint::new(-10.0, 10.0, 12);
+        assert_eq!(round_trip(&-25.0, fixed).0, -10.0);
+        assert_eq!(round_trip(&f32::MAX, fixed).0, 10.0);
+        assert_eq!(fixed.decode(u32::MAX), 10.0);
+    }
+
+    #[test]
+    fn vector_round_trip() {
+        let fixed = FixedPoint::new(-100.0, 100.0, 16);
+        let value = Vec3::new(-42.5, 0.01, 99.0);
+        let (decoded, len) = round_trip(&value, fixed);
+        assert_eq!(len, 6);
+        assert!((decoded - value).abs().max_element() <= fixed.precision());
+    }
+
+    #[test]
+    fn quat_round_trip() {
+        let rotations = [
+            Quat::IDENTITY,
+            Quat::from_rotation_y(1.0),
+            Quat::from_euler(EulerRot::XYZ, 0.3, -2.1, 1.2),
+            Quat::from_xyzw(0.5, 0.5, 0.5, 0.5),
+        ];
+        for bits in [8, 16, 32] {
+            let range = smallest_three_range(bits);
+            for rotation in rotations {
+                let (decoded, len) = round_trip(&rotation, FixedPoint::new(0.0, 1.0, bits));
+                assert_eq!(len, 1 + 3 * bits.div_ceil(8) as usize);
+                // `q` and `-q` are the same rotation
+                let error = (Vec4::from(decoded) - Vec4::from(rotation))
+                    .abs()
+                    .max_element()
+                    .min((Vec4::from(decoded) + Vec4::from(rotation)).abs().max_element());
+                // The largest component is rebuilt from the other three, each at most its size
+                assert!(
+                    error <= 3.0 * range.precision(),
+                    "{rotation} decoded as {decoded} with {bits} bits"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn quat_flips_sign() {
+        let rotation = Quat::from_xyzw(0.1, -0.2, 0.3, -0.9).normalize();
+        let (decoded, _) = round_trip(&rotation, FixedPoint::new(0.0, 1.0, 16));
+        // `-q` is sent, which is the same rotation
+        assert!(decoded.w > 0.0);
+        let error = (Vec4::from(decoded) + Vec4::from(rotation)).abs().max_element();
+        assert!(error <= 3.0 * smallest_three_range(16).precision());
+    }
+}
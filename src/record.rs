@@ -0,0 +1,379 @@
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use bevy::{ecs::component::Mutable, platform::collections::HashMap, prelude::*};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    events::SnapshotReceived,
+    interpolation::{
+        snapshot_interpolation_system, Interpolate, SnapshotBuffer, SnapshotInterpolationConfig,
+    },
+    profile::InterpolationProfiles,
+    Interpolated,
+};
+
+/// A single snapshot arrival.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct SnapshotRecord {
+    /// Client entity the snapshot was written to.
+    pub entity: u64,
+    /// Type name of the component.
+    pub component: String,
+    /// Server tick of the snapshot.
+    pub tick: u32,
+    /// Seconds since recording started.
+    pub arrival_time: f32,
+    /// Component value encoded with postcard.
+    pub value: Vec<u8>,
+}
+
+/// Writes every snapshot arrival of components registered with `record_snapshots` to a file.
+///
+/// Records are stored as postcard with a little endian `u32` length prefix each.
+#[derive(Resource)]
+pub struct SnapshotRecorder {
+    writer: BufWriter<File>,
+    started_at: Option<f32>,
+}
+
+impl SnapshotRecorder {
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self {
+            writer: BufWriter::new(File::create(path)?),
+            started_at: None,
+        })
+    }
+
+    pub fn write(&mut self, record: &SnapshotRecord) -> io::Result<()> {
+        let bytes = postcard::to_allocvec(record).map_err(io::Error::other)?;
+        self.writer.write_all(&(bytes.len() as u32).to_le_bytes())?;
+        self.writer.write_all(&bytes)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Reads all records of a file written by `SnapshotRecorder`.
+pub fn read_snapshot_records(path: impl AsRef<Path>) -> io::Result<Vec<SnapshotRecord>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut records = Vec::new();
+    let mut len = [0; 4];
+    loop {
+        match reader.read_exact(&mut len) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e),
+        }
+        let mut bytes = vec![0; u32::from_le_bytes(len) as usize];
+        reader.read_exact(&mut bytes)?;
+        records.push(postcard::from_bytes(&bytes).map_err(io::Error::other)?);
+    }
+    Ok(records)
+}
+
+/// Records a snapshot arrival of `C`.
+pub fn record_snapshot_system<C: Component + Interpolate + Clone + Serialize>(
+    trigger: Trigger<SnapshotReceived<C>>,
+    recorder: Option<ResMut<SnapshotRecorder>>,
+    time: Res<Time>,
+) {
+    let Some(mut recorder) = recorder else {
+        return;
+    };
+    let value = match postcard::to_allocvec(&trigger.event().value) {
+        Ok(value) => value,
+        Err(e) => {
+            error!("unable to serialize snapshot: {e}");
+            return;
+        }
+    };
+
+    let started_at = *recorder.started_at.get_or_insert(time.elapsed_secs());
+    let record = SnapshotRecord {
+        entity: trigger.target().to_bits(),
+        component: std::any::type_name::<C>().to_string(),
+        tick: trigger.event().tick,
+        arrival_time: time.elapsed_secs() - started_at,
+        value,
+    };
+    if let Err(e) = recorder.write(&record) {
+        error!("unable to record snapshot: {e}");
+    }
+}
+
+/// Flushes the recorder at the end of every frame.
+pub fn flush_recorder_system(mut recorder: ResMut<SnapshotRecorder>) {
+    if let Err(e) = recorder.flush() {
+        error!("unable to flush snapshot recording: {e}");
+    }
+}
+
+/// Feeds a recording back into snapshot buffers without a server.
+///
+/// Components are added with `AppSnapshotRecordExt::playback_snapshots`.
+/// Doesn't need `SnapshotInterpolationPlugin`, the interpolation resources are added if missing.
+pub struct SnapshotPlaybackPlugin {
+    pub path: PathBuf,
+    /// Tick rate of the server the snapshots were recorded from.
+    pub max_tick_rate: u16,
+}
+
+/// Records that have not been played back yet.
+#[derive(Resource, Default)]
+pub struct SnapshotPlayback {
+    pub records: VecDeque<SnapshotRecord>,
+    /// Seconds since playback started.
+    pub elapsed: f32,
+    /// Recorded entities mapped to their playback entities.
+    pub entities: HashMap<u64, Entity>,
+}
+
+impl SnapshotPlayback {
+    pub fn new(records: impl IntoIterator<Item = SnapshotRecord>) -> Self {
+        Self {
+            records: records.into_iter().collect(),
+            ..default()
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.records.is_empty()
+    }
+}
+
+impl Plugin for SnapshotPlaybackPlugin {
+    fn build(&self, app: &mut App) {
+        let records = read_snapshot_records(&self.path).unwrap_or_else(|e| {
+            panic!("unable to read snapshot recording {:?}: {e}", self.path)
+        });
+        if !app.world().contains_resource::<SnapshotInterpolationConfig>() {
+            app.insert_resource(SnapshotInterpolationConfig {
+                max_tick_rate: self.max_tick_rate,
+            });
+        }
+        app.init_resource::<InterpolationProfiles>()
+            .insert_resource(SnapshotPlayback::new(records))
+            .add_systems(PreUpdate, playback_time_system);
+    }
+}
+
+/// Advances the playback clock.
+pub fn playback_time_system(mut playback: ResMut<SnapshotPlayback>, time: Res<Time>) {
+    playback.elapsed += time.delta_secs();
+}
+
+/// Inserts recorded snapshots of `C` whose arrival time has been reached.
+pub fn playback_snapshot_system<
+    C: Component<Mutability=Mutable> + Interpolate + Clone + DeserializeOwned,
+>(
+    mut playback: ResMut<SnapshotPlayback>,
+    mut q: Query<&mut SnapshotBuffer<C>>,
+    mut commands: Commands,
+) {
+    let component = std::any::type_name::<C>();
+    let playback = &mut *playback;
+    // Buffers of new entities are inserted at the end, several records may be due at once
+    let mut spawned: HashMap<Entity, SnapshotBuffer<C>> = HashMap::default();
+    while let Some(index) = playback
+        .records
+        .iter()
+        .take_while(|record| record.arrival_time <= playback.elapsed)
+        .position(|record| record.component == component)
+    {
+        let Some(record) = playback.records.remove(index) else {
+            break;
+        };
+        let value: C = match postcard::from_bytes(&record.value) {
+            Ok(value) => value,
+            Err(e) => {
+                error!("unable to deserialize recorded snapshot: {e}");
+                continue;
+            }
+        };
+
+        let entity = *playback
+            .entities
+            .entry(record.entity)
+            .or_insert_with(|| commands.spawn(Interpolated).id());
+        if let Ok(mut snapshot_buffer) = q.get_mut(entity) {
+            snapshot_buffer.insert(value, record.tick);
+        } else {
+            spawned
+                .entry(entity)
+                .or_insert_with(SnapshotBuffer::new)
+                .insert(value, record.tick);
+        }
+    }
+
+    for (entity, snapshot_buffer) in spawned {
+        commands
+            .entity(entity)
+            .insert((snapshot_buffer.latest_snapshot(), snapshot_buffer));
+    }
+}
+
+pub trait AppSnapshotRecordExt {
+    /// Record every snapshot arrival of a component registered for interpolation.
+    /// Recording happens while a `SnapshotRecorder` resource exists.
+    fn record_snapshots<C>(&mut self) -> &mut Self
+    where
+        C: Component + Interpolate + Clone + Serialize;
+
+    /// Play back recorded snapshots of a component and interpolate them.
+    /// Requires the `SnapshotPlaybackPlugin`.
+    fn playback_snapshots<C>(&mut self) -> &mut Self
+    where
+        C: Component<Mutability=Mutable> + Interpolate + Clone + DeserializeOwned;
+}
+
+impl AppSnapshotRecordExt for App {
+    fn record_snapshots<C>(&mut self) -> &mut Self
+    where
+        C: Component + Interpolate + Clone + Serialize,
+    {
+        if !self.is_plugin_added::<RecorderFlushPlugin>() {
+            self.add_plugins(RecorderFlushPlugin);
+        }
+        self.add_observer(record_snapshot_system::<C>)
+    }
+
+    fn playback_snapshots<C>(&mut self) -> &mut Self
+    where
+        C: Component<Mutability=Mutable> + Interpolate + Clone + DeserializeOwned,
+    {
+        self.add_systems(
+            PreUpdate,
+            (
+                playback_snapshot_system::<C>.after(playback_time_system),
+                snapshot_interpolation_system::<C>,
+            )
+                .chain()
+                .run_if(resource_exists::<SnapshotPlayback>),
+        )
+    }
+}
+
+struct RecorderFlushPlugin;
+
+impl Plugin for RecorderFlushPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Last,
+            flush_recorder_system.run_if(resource_exists::<SnapshotRecorder>),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Component, Deserialize, Serialize, Clone, Debug, PartialEq)]
+    struct Health(f32);
+
+    impl Interpolate for Health {
+        fn interpolate(&self, other: Self, t: f32) -> Self {
+            Self(self.0 + (other.0 - self.0) * t)
+        }
+    }
+
+    fn record(entity: u64, tick: u32, arrival_time: f32, value: f32) -> SnapshotRecord {
+        SnapshotRecord {
+            entity,
+            component: std::any::type_name::<Health>().to_string(),
+            tick,
+            arrival_time,
+            value: postcard::to_allocvec(&Health(value)).unwrap(),
+        }
+    }
+
+    #[test]
+    fn write_read_round_trip() {
+        let path = std::env::temp_dir().join(format!(
+            "bevy_replicon_snap_record_{}.bin",
+            std::process::id()
+        ));
+        let records = [record(1, 10, 0.0, 1.0), record(2, 11, 0.5, -3.5)];
+        let mut recorder = SnapshotRecorder::create(&path).unwrap();
+        for record in records.iter() {
+            recorder.write(record).unwrap();
+        }
+        recorder.flush().unwrap();
+
+        let read = read_snapshot_records(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(read.len(), records.len());
+        for (read, record) in read.iter().zip(records.iter()) {
+            assert_eq!(read.entity, record.entity);
+            assert_eq!(read.component, record.component);
+            assert_eq!(read.tick, record.tick);
+            assert_eq!(read.arrival_time, record.arrival_time);
+            assert_eq!(read.value, record.value);
+        }
+    }
+
+    #[test]
+    fn playback_keeps_records_due_in_one_frame() {
+        let mut playback = SnapshotPlayback::new([
+            record(1, 1, 0.0, 1.0),
+            record(1, 2, 0.1, 2.0),
+            record(2, 2, 0.1, 5.0),
+            record(1, 3, 0.2, 3.0),
+            record(1, 4, 2.0, 4.0),
+        ]);
+        playback.elapsed = 1.0;
+
+        let mut app = App::new();
+        app.insert_resource(playback)
+            .add_systems(Update, playback_snapshot_system::<Health>);
+        app.update();
+
+        let playback = app.world().resource::<SnapshotPlayback>();
+        assert_eq!(playback.records.len(), 1);
+        let first = playback.entities[&1];
+        let second = playback.entities[&2];
+
+        let snapshot_buffer = app.world().get::<SnapshotBuffer<Health>>(first).unwrap();
+        assert_eq!(snapshot_buffer.ticks, [1, 2, 3]);
+        assert_eq!(snapshot_buffer.buffer, [Health(1.0), Health(2.0), Health(3.0)]);
+        assert_eq!(app.world().get::<Health>(first), Some(&Health(3.0)));
+        assert_eq!(app.world().get::<Health>(second), Some(&Health(5.0)));
+    }
+
+    #[test]
+    fn playback_runs_without_interpolation_plugin() {
+        let path = std::env::temp_dir().join(format!(
+            "bevy_replicon_snap_playback_{}.bin",
+            std::process::id()
+        ));
+        let mut recorder = SnapshotRecorder::create(&path).unwrap();
+        recorder.write(&record(1, 1, 0.0, 1.0)).unwrap();
+        recorder.write(&record(1, 2, 0.0, 2.0)).unwrap();
+        recorder.flush().unwrap();
+
+        let mut app = App::new();
+        app.add_plugins((
+            MinimalPlugins,
+            SnapshotPlaybackPlugin {
+                path: path.clone(),
+                max_tick_rate: 60,
+            },
+        ))
+        .playback_snapshots::<Health>();
+        std::fs::remove_file(&path).unwrap();
+        app.update();
+        app.update();
+
+        let playback = app.world().resource::<SnapshotPlayback>();
+        assert!(playback.is_finished());
+        let entity = playback.entities[&1];
+        assert!(app.world().get::<Health>(entity).is_some());
+    }
+}
@@ -0,0 +1,90 @@
+use std::mem;
+
+use bevy::{ecs::component::Mutable, prelude::*, transform::TransformSystem};
+
+use crate::{interpolation::Interpolate, prediction::Predicted};
+
+/// Values of `C` after the previous and the latest `FixedUpdate` step of a predicted entity.
+#[derive(Component)]
+pub struct FixedStepHistory<C: Component> {
+    pub previous: C,
+    pub current: C,
+}
+
+/// Mapping used by `smooth_predicted_into` to write the smoothed `C` into `Dst`.
+#[derive(Resource)]
+pub struct FixedStepTarget<C, Dst> {
+    pub map: fn(&C, &mut Dst),
+}
+
+/// Records the value of `C` after every fixed step.
+pub fn fixed_step_record_system<C: Component + Clone>(
+    mut q: Query<(Entity, &C, Option<&mut FixedStepHistory<C>>), With<Predicted>>,
+    mut commands: Commands,
+) {
+    for (e, component, history) in q.iter_mut() {
+        match history {
+            Some(mut history) => {
+                let history = &mut *history;
+                history.previous = mem::replace(&mut history.current, component.clone());
+            }
+            None => {
+                commands.entity(e).insert(FixedStepHistory {
+                    previous: component.clone(),
+                    current: component.clone(),
+                });
+            }
+        }
+    }
+}
+
+/// Writes the value between the two latest fixed steps into `Dst`, so predicted entities
+/// move smoothly when the frame rate doesn't match the fixed step rate.
+pub fn fixed_step_smoothing_system<
+    C: Component + Interpolate + Clone,
+    Dst: Component<Mutability=Mutable> + Default,
+>(
+    mut q: Query<(Entity, &FixedStepHistory<C>, Option<&mut Dst>), With<Predicted>>,
+    target: Res<FixedStepTarget<C, Dst>>,
+    fixed_time: Res<Time<Fixed>>,
+    mut commands: Commands,
+) {
+    let t = fixed_time.overstep_fraction();
+    for (e, history, visual) in q.iter_mut() {
+        let value = history.previous.interpolate(history.current.clone(), t);
+        match visual {
+            Some(mut visual) => (target.map)(&value, &mut visual),
+            None => {
+                let mut visual = Dst::default();
+                (target.map)(&value, &mut visual);
+                commands.entity(e).insert(visual);
+            }
+        }
+    }
+}
+
+pub trait AppSmoothingExt {
+    /// Smooth a component of predicted entities that is simulated in `FixedUpdate`.
+    /// The value between the two latest fixed steps, weighted by `Time<Fixed>::overstep_fraction`,
+    /// is written into `Dst` with `map`, separate from network snapshot interpolation.
+    fn smooth_predicted_into<C, Dst>(&mut self, map: fn(&C, &mut Dst)) -> &mut Self
+    where
+        C: Component + Interpolate + Clone,
+        Dst: Component<Mutability=Mutable> + Default;
+}
+
+impl AppSmoothingExt for App {
+    fn smooth_predicted_into<C, Dst>(&mut self, map: fn(&C, &mut Dst)) -> &mut Self
+    where
+        C: Component + Interpolate + Clone,
+        Dst: Component<Mutability=Mutable> + Default,
+    {
+        self.insert_resource(FixedStepTarget { map })
+            .add_systems(FixedLast, fixed_step_record_system::<C>)
+            .add_systems(
+                PostUpdate,
+                fixed_step_smoothing_system::<C, Dst>
+                    .before(TransformSystem::TransformPropagate),
+            )
+    }
+}
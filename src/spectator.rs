@@ -0,0 +1,185 @@
+use bevy::{ecs::component::Mutable, prelude::*};
+use bevy_replicon::{client::ServerUpdateTick, prelude::*};
+
+use crate::{
+    interpolation::{
+        Interpolate, InterpolationTarget, SnapshotBuffer, SnapshotInterpolationConfig,
+    },
+    Interpolated, InterpolationSet,
+};
+
+/// Turns the client into a spectator.
+///
+/// Every entity is interpolated, snapshots are buffered for a long window and displayed with a
+/// large delay, driven by the controllable `SpectatorClock`.
+pub struct SpectatorPlugin {
+    /// Seconds the displayed timeline lags behind the latest server update.
+    pub delay: f32,
+    /// Server ticks of snapshots kept per entity and component, which can be seeked within.
+    pub window_ticks: u32,
+}
+
+impl Default for SpectatorPlugin {
+    fn default() -> Self {
+        Self {
+            delay: 2.0,
+            window_ticks: 300,
+        }
+    }
+}
+
+#[derive(Resource, Debug)]
+pub struct SpectatorConfig {
+    pub delay: f32,
+    pub window_ticks: u32,
+}
+
+/// Playback clock of a spectator, measured in fractional server ticks.
+#[derive(Resource, Debug, Default)]
+pub struct SpectatorClock {
+    /// Server tick that is currently displayed.
+    pub tick: f64,
+    /// Playback speed, `1.0` is real time.
+    pub speed: f32,
+    pub paused: bool,
+    /// Oldest and latest server tick that can be displayed.
+    pub window: (u32, u32),
+    started: bool,
+}
+
+impl SpectatorClock {
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    /// Jumps to a server tick, clamped to the buffered window.
+    pub fn seek(&mut self, tick: f64) {
+        self.tick = tick.clamp(self.window.0 as f64, self.window.1 as f64);
+    }
+
+    /// Jumps by a number of seconds, negative values go back in time.
+    pub fn seek_by(&mut self, seconds: f32, tick_rate: u16) {
+        self.seek(self.tick + (seconds * tick_rate as f32) as f64);
+    }
+}
+
+impl Plugin for SpectatorPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(SpectatorConfig {
+            delay: self.delay,
+            window_ticks: self.window_ticks,
+        })
+            .insert_resource(SpectatorClock {
+                speed: 1.0,
+                ..default()
+            })
+            .add_systems(
+                PreUpdate,
+                spectator_clock_system
+                    .in_set(InterpolationSet::Interpolate)
+                    .run_if(client_connected),
+            );
+    }
+}
+
+/// Advances the spectator clock and keeps it inside the buffered window.
+pub fn spectator_clock_system(
+    mut clock: ResMut<SpectatorClock>,
+    spectator: Res<SpectatorConfig>,
+    config: Res<SnapshotInterpolationConfig>,
+    update_tick: Res<ServerUpdateTick>,
+    time: Res<Time>,
+) {
+    let latest = update_tick.get();
+    if latest == 0 {
+        return;
+    }
+    let tick_rate = config.max_tick_rate as f32;
+    let delay_ticks = (spectator.delay * tick_rate) as u32;
+    clock.window = (latest.saturating_sub(spectator.window_ticks), latest);
+
+    if !clock.started {
+        clock.started = true;
+        clock.tick = latest.saturating_sub(delay_ticks) as f64;
+    } else if !clock.paused {
+        clock.tick += (time.delta_secs() * tick_rate * clock.speed) as f64;
+    }
+    let tick = clock.tick;
+    clock.seek(tick);
+}
+
+/// Lets every snapshot buffer hold the whole spectator window, even if a snapshot arrives
+/// every tick.
+pub fn spectator_buffer_init_system<C: Component + Interpolate + Clone>(
+    mut q: Query<&mut SnapshotBuffer<C>, Added<SnapshotBuffer<C>>>,
+    spectator: Res<SpectatorConfig>,
+) {
+    for mut snapshot_buffer in q.iter_mut() {
+        snapshot_buffer.capacity = spectator.window_ticks as usize + 1;
+    }
+}
+
+/// Drops snapshots that left the spectator window.
+///
+/// The latest snapshot at or before the start of the window is kept to sample from.
+pub fn spectator_buffer_prune_system<C: Component + Interpolate + Clone>(
+    mut q: Query<&mut SnapshotBuffer<C>>,
+    spectator: Res<SpectatorConfig>,
+    update_tick: Res<ServerUpdateTick>,
+) {
+    let oldest = update_tick.get().saturating_sub(spectator.window_ticks);
+    for mut snapshot_buffer in q.iter_mut() {
+        let stale = snapshot_buffer
+            .ticks
+            .iter()
+            .skip(1)
+            .take_while(|tick| **tick <= oldest)
+            .count();
+        if stale == 0 {
+            continue;
+        }
+        snapshot_buffer.buffer.drain(..stale);
+        snapshot_buffer.ticks.drain(..stale);
+    }
+}
+
+/// Displays the value at the spectator clock.
+pub fn spectator_interpolation_system<C: Component<Mutability=Mutable> + Interpolate + Clone>(
+    mut q: Query<(&mut C, &SnapshotBuffer<C>), With<Interpolated>>,
+    clock: Res<SpectatorClock>,
+) {
+    for (mut component, snapshot_buffer) in q.iter_mut() {
+        if let Some(value) = snapshot_buffer.sample(clock.tick) {
+            *component = value;
+        }
+    }
+}
+
+/// Displays the value of `Src` at the spectator clock in `Dst`.
+pub fn spectator_interpolation_into_system<
+    Src: Component + Interpolate + Clone,
+    Dst: Component<Mutability=Mutable> + Default,
+>(
+    mut q: Query<(Entity, &SnapshotBuffer<Src>, Option<&mut Dst>), With<Interpolated>>,
+    target: Res<InterpolationTarget<Src, Dst>>,
+    clock: Res<SpectatorClock>,
+    mut commands: Commands,
+) {
+    for (e, snapshot_buffer, visual) in q.iter_mut() {
+        let Some(value) = snapshot_buffer.sample(clock.tick) else {
+            continue;
+        };
+        match visual {
+            Some(mut visual) => (target.map)(&value, &mut visual),
+            None => {
+                let mut visual = Dst::default();
+                (target.map)(&value, &mut visual);
+                commands.entity(e).insert(visual);
+            }
+        }
+    }
+}
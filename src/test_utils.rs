@@ -0,0 +1,43 @@
+use bevy::{prelude::*, state::app::StatesPlugin};
+use bevy_replicon::{
+    prelude::*,
+    server::{ServerPlugin, TickPolicy},
+    test_app::ServerTestAppExt,
+};
+
+use crate::SnapshotInterpolationPlugin;
+
+/// A server and a client app connected to each other, along with the client entity on the server.
+///
+/// `setup` runs on both apps before connecting, so registrations match.
+pub(crate) fn connected_apps(setup: impl Fn(&mut App)) -> (App, App, Entity) {
+    connected_apps_with(TickPolicy::EveryFrame, setup)
+}
+
+/// Like `connected_apps`, but the server increments its tick with `tick_policy`.
+pub(crate) fn connected_apps_with(
+    tick_policy: TickPolicy,
+    setup: impl Fn(&mut App),
+) -> (App, App, Entity) {
+    let mut server_app = App::new();
+    let mut client_app = App::new();
+    for app in [&mut server_app, &mut client_app] {
+        app.add_plugins((
+            MinimalPlugins,
+            StatesPlugin,
+            RepliconPlugins.set(ServerPlugin {
+                tick_policy,
+                ..Default::default()
+            }),
+            SnapshotInterpolationPlugin { max_tick_rate: 60 },
+        ));
+        setup(app);
+    }
+    server_app.connect_client(&mut client_app);
+    let client_entity = server_app
+        .world_mut()
+        .query_filtered::<Entity, With<AuthorizedClient>>()
+        .single(server_app.world())
+        .unwrap();
+    (server_app, client_app, client_entity)
+}